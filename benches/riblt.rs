@@ -0,0 +1,156 @@
+// Benchmarks for the hot paths: generating coded symbols, collapsing, peeling and the index
+// mapping, parameterised by set size, difference size and symbol width.
+//
+// Run them with `cargo bench`, or pick some with e.g. `cargo bench -- peel_all_symbols`.
+// Criterion keeps the previous results in target/criterion and reports any change.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use riblt::{ByteSymbol, RandomMapping, RatelessIBLT, UnmanagedRatelessIBLT};
+use std::hint::black_box;
+
+const SET_SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const DIFFERENCE_SIZES: [usize; 3] = [10, 100, 1_000];
+
+// Generating coded symbols costs the same however many differences there are, so each one takes
+// a fixed number of them
+const CODED_SYMBOLS: usize = 1_000;
+
+// The set that collapsing and peeling are measured against
+const COLLAPSE_SET_SIZE: usize = 10_000;
+
+// splitmix64, so the symbols look random but are the same on every run
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+fn symbol<const N: usize>(seed: u64) -> ByteSymbol<N> {
+    let mut bytes = [0u8; N];
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        let word = mix(seed.wrapping_mul(16).wrapping_add(i as u64)).to_le_bytes();
+        chunk.copy_from_slice(&word[..chunk.len()]);
+    }
+    ByteSymbol(bytes)
+}
+
+fn symbols<const N: usize>(seeds: core::ops::Range<u64>) -> Vec<ByteSymbol<N>> {
+    seeds.map(symbol).collect()
+}
+
+// The local and remote sets share everything but the difference, which is split evenly between
+// the two sides. Returns the collapse of the first len coded symbols.
+fn collapsed<const N: usize>(
+    set_size: usize,
+    difference: usize,
+    len: usize,
+) -> (
+    RatelessIBLT<ByteSymbol<N>, Vec<ByteSymbol<N>>>,
+    UnmanagedRatelessIBLT<ByteSymbol<N>>,
+) {
+    let local_only = (difference / 2) as u64;
+    let remote_only = (difference - difference / 2) as u64;
+    let common = set_size as u64 - local_only;
+    let mut local = RatelessIBLT::new(symbols::<N>(0..common + local_only));
+    let mut remote = RatelessIBLT::new(symbols::<N>(local_only..local_only + common + remote_only));
+    local.extend_coded_symbols(len - 1).unwrap();
+    remote.extend_coded_symbols(len - 1).unwrap();
+
+    let received = UnmanagedRatelessIBLT::with_table(remote.coded_symbols.prefix(len));
+    (local, received)
+}
+
+// Enough coded symbols to decode the difference, the paper needs about 1.35 per difference
+fn decodable_len(difference: usize) -> usize {
+    2 * difference + 100
+}
+
+fn bench_extend_coded_symbols<const N: usize>(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("extend_coded_symbols/{}_bytes", N));
+    group.sample_size(10);
+    for set_size in SET_SIZES {
+        let set = symbols::<N>(0..set_size as u64);
+        group.throughput(Throughput::Bytes((set_size * N) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(set_size), &set, |b, set| {
+            b.iter_batched(
+                || RatelessIBLT::new(set.clone()),
+                |mut iblt| {
+                    iblt.extend_coded_symbols(CODED_SYMBOLS - 1).unwrap();
+                    iblt
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_collapse<const N: usize>(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("collapse/{}_bytes", N));
+    for difference in DIFFERENCE_SIZES {
+        let len = decodable_len(difference);
+        let (mut local, received) = collapsed::<N>(COLLAPSE_SET_SIZE, difference, len);
+        group.throughput(Throughput::Elements(len as u64));
+        group.bench_function(BenchmarkId::from_parameter(difference), |b| {
+            b.iter(|| local.collapse(black_box(&received)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_peel_all_symbols<const N: usize>(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("peel_all_symbols/{}_bytes", N));
+    for difference in DIFFERENCE_SIZES {
+        let (mut local, received) =
+            collapsed::<N>(COLLAPSE_SET_SIZE, difference, decodable_len(difference));
+        let collapsed = local.collapse(&received).unwrap();
+        group.throughput(Throughput::Elements(difference as u64));
+        group.bench_function(BenchmarkId::from_parameter(difference), |b| {
+            b.iter_batched(
+                || UnmanagedRatelessIBLT::with_table(collapsed.coded_symbols.clone()),
+                |mut collapsed| {
+                    let peeled = collapsed.peel_all_symbols();
+                    assert_eq!(peeled.len(), difference);
+                    peeled
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// Every index each symbol maps to in a block of coded symbols, the work done per symbol when
+// encoding. About 1 + ln(len) indexes each.
+fn bench_random_mapping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("random_mapping");
+    let set = symbols::<8>(0..1_000);
+    for len in [1_000, 100_000, 10_000_000] {
+        let indexes: usize = set
+            .iter()
+            .map(|s| RandomMapping::new(s).take_while(|&i| i < len).count())
+            .sum();
+        group.throughput(Throughput::Elements(indexes as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &len, |b, &len| {
+            b.iter(|| {
+                set.iter()
+                    .map(|s| RandomMapping::new(s).take_while(|&i| i < len).count())
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_extend_coded_symbols::<8>,
+    bench_extend_coded_symbols::<32>,
+    bench_collapse::<8>,
+    bench_collapse::<32>,
+    bench_peel_all_symbols::<8>,
+    bench_peel_all_symbols::<32>,
+    bench_random_mapping,
+);
+criterion_main!(benches);
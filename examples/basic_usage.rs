@@ -1,7 +1,5 @@
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time::Instant;
-use bincode;
-use riblt;
 
 // Example implementation of a struct that implements the Symbol trait
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -12,6 +10,8 @@ struct SimpleSymbol {
 
 impl riblt::Symbol for SimpleSymbol {
     const BYTE_ARRAY_LENGTH: usize = 16;
+    // hash_ is overridden below, so the hash scheme has to change too
+    const HASH_SCHEME: u32 = 1000;
     // The Symbol trait only requires that the type can be encoded to bytes.
     // In this function we are doing so in a fairly manual way.
     // I would recommend using a serialization library like bincode.
@@ -54,42 +54,69 @@ fn main() {
 
     // A set of symbols we have on our local server
     let local_items: HashSet<SimpleSymbol> = HashSet::from([
-        SimpleSymbol { unique_id: 7, timestamp: 0},
-        SimpleSymbol { unique_id: 15, timestamp: 0},
-        SimpleSymbol { unique_id: 16, timestamp: 0},
-        SimpleSymbol { unique_id: 17, timestamp: 0}, //local only
+        SimpleSymbol {
+            unique_id: 7,
+            timestamp: 0,
+        },
+        SimpleSymbol {
+            unique_id: 15,
+            timestamp: 0,
+        },
+        SimpleSymbol {
+            unique_id: 16,
+            timestamp: 0,
+        },
+        SimpleSymbol {
+            unique_id: 17,
+            timestamp: 0,
+        }, //local only
     ]);
     let mut managed_local_iblt = riblt::RatelessIBLT::new(local_items);
 
     // A set of symbols on a remote server
     let remote_items: HashSet<SimpleSymbol> = HashSet::from([
-        SimpleSymbol { unique_id: 7, timestamp: 0},
-        SimpleSymbol { unique_id: 15, timestamp: 0},
-        SimpleSymbol { unique_id: 16, timestamp: 0},
-        SimpleSymbol { unique_id: 18, timestamp: 0}, //remote only
+        SimpleSymbol {
+            unique_id: 7,
+            timestamp: 0,
+        },
+        SimpleSymbol {
+            unique_id: 15,
+            timestamp: 0,
+        },
+        SimpleSymbol {
+            unique_id: 16,
+            timestamp: 0,
+        },
+        SimpleSymbol {
+            unique_id: 18,
+            timestamp: 0,
+        }, //remote only
     ]);
     let mut managed_remote_iblt = riblt::RatelessIBLT::new(remote_items);
 
-    let mut local_copy_of_remote : riblt::UnmanagedRatelessIBLT<SimpleSymbol> = riblt::UnmanagedRatelessIBLT::new();
+    // Each coded symbol that arrives is collapsed against our own coded symbol at the same index
+    // and handed to the decoder, so each attempt only does work for the new coded symbol rather
+    // than collapsing and peeling everything received so far.
+    let mut decoder: riblt::Decoder<SimpleSymbol> = riblt::Decoder::new();
     for i in 0..20 {
         println!("Getting coded symbol {}", i);
-        let one_coded_symbol = managed_remote_iblt.get_coded_symbol(i);
+        let one_coded_symbol = managed_remote_iblt.get_coded_symbol(i).unwrap();
         let encoded_coded_symbol = bincode::serialize(&one_coded_symbol).unwrap();
-        let decoded_coded_symbol : riblt::CodedSymbol<SimpleSymbol> = bincode::deserialize(&encoded_coded_symbol).unwrap();
-        local_copy_of_remote.add_coded_symbol(&decoded_coded_symbol);
-
-        let mut collapsed = managed_local_iblt.collapse(&local_copy_of_remote);
-        let peeled_symbols = collapsed.peel_all_symbols();
-        if collapsed.is_empty() {
+        let decoded_coded_symbol: riblt::CodedSymbol<SimpleSymbol> =
+            bincode::deserialize(&encoded_coded_symbol).unwrap();
+
+        decoder.add_coded_symbol(
+            &managed_local_iblt
+                .collapse_coded_symbol(i, &decoded_coded_symbol)
+                .unwrap(),
+        );
+        if decoder.is_empty() {
             println!("Peeled all symbols");
-            println!("{:?}", peeled_symbols);
+            println!("{:?}", decoder.recovered_so_far());
             break;
         }
     }
 
-
-
-
     let mut test_items: HashSet<SimpleSymbol> = HashSet::new();
     for i in 0..10_000_000 {
         test_items.insert(SimpleSymbol {
@@ -100,29 +127,24 @@ fn main() {
     let start = Instant::now();
     let mut test_riblt = riblt::RatelessIBLT::new(test_items);
     let coded_symbols_to_get = 10_000_000;
-    let coded_symbol = test_riblt.get_coded_symbol(coded_symbols_to_get);
+    let coded_symbol = test_riblt.get_coded_symbol(coded_symbols_to_get).unwrap();
     let duration = start.elapsed();
 
     let encoded_coded_symbol = bincode::serialize(&coded_symbol).unwrap();
     let encoded_length = encoded_coded_symbol.len();
     println!("encoded CodedSymbol length {:?}", encoded_length);
-    println!("encoding bandwidth {:?} Mb/s", coded_symbols_to_get as f64 * 8.0 * encoded_length as f64 / duration.as_secs_f64() / 1_000_000.0);
+    println!(
+        "encoding bandwidth {:?} Mb/s",
+        coded_symbols_to_get as f64 * 8.0 * encoded_length as f64
+            / duration.as_secs_f64()
+            / 1_000_000.0
+    );
 
     println!("Time building codedSymbols is: {:?}", duration);
 
-    // The time to produce 1,000,000 coded symbols (40 bytes each) from a set of 10,000,000 symbols is about 20 seconds
-    // on my machine.
-    //
-    // This gives a 17 Mb/s encoding bandwidth.
-    //
-    // Encoding 10x more CodedSymbols takes about 34 seconds, which is a 98 Mb/s encoding bandwidth.
-    //
-    // This scales linearly with the number of symbols in the set.
-    //
-    // Next up is to test the decoding bandwidth of a collapsed RIBLT.
-    //
-    // overall this is looking quite positive, as we can encoded about as fast as we could get data
-    // off a disk. And decoding should remain proportional to the differences in the sets.
+    // This is only a rough timing of a single run. The benches/ directory has criterion benchmarks
+    // of encoding, collapsing, peeling and the index mapping for several set sizes, difference
+    // sizes and symbol widths, run them with `cargo bench`.
 
     // let local_coded_symbol_block = riblt::produce_block(local_items, 0);
     // let remote_coded_symbol_block = riblt::produce_block(remote_items, 0);
@@ -136,7 +158,6 @@ fn main() {
     // let test_data_decoded = SimpleSymbol::decode_from_bytes(&test_data_encoded);
     // println!("{:?}", test_data_decoded);
 
-
     // println!("{:?}", managed_local_iblt.coded_symbols);
     // managed_local_iblt.get_coded_symbol(1);
     // managed_local_iblt.get_coded_symbol(30);
@@ -0,0 +1,325 @@
+// Measures the communication overhead: how many coded symbols it takes to decode a difference of
+// d symbols, over many random trials. The paper reports about 1.35 * d on average for large d.
+//
+//     cargo run --release --example overhead -- [--trials N] [--mapping M,...] [d ...]
+//
+// The default is 100 trials of d = 10, 100 and 1000 with the riblt mapping. The mappings are:
+//
+// - riblt: the real thing. Both sets are encoded with RatelessIBLT, and the remote's coded
+//   symbols are collapsed and added to a Decoder one at a time until it is empty.
+// - simulated: RandomMapping, but only the indexes are simulated, without any sums or hashes.
+//   Much faster, and it should give the same numbers as riblt.
+// - alpha=A: each symbol maps to index i with probability 1 / (1 + A * i), decided independently
+//   for every index. RandomMapping is alpha=0.5, other values compare other degree
+//   distributions. Slower, as every index up to the last coded symbol is visited for every
+//   symbol.
+//
+// For each mapping and d it prints the mean (and the mean divided by d, the overhead), the
+// standard deviation and some percentiles of the number of coded symbols needed.
+
+use riblt::{ByteSymbol, Decoder, RandomMapping, RatelessIBLT, Symbol};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::process::ExitCode;
+
+// The symbols both sets have, these cancel out when collapsing
+const COMMON: u64 = 1_000;
+
+// Give up on a trial after this many coded symbols per difference, nothing should get close
+const MAX_CODED_SYMBOLS_PER_DIFFERENCE: usize = 50;
+
+type Id = ByteSymbol<8>;
+
+#[derive(Clone, Copy, Debug)]
+enum Mapping {
+    Riblt,
+    Simulated,
+    Alpha(f64),
+}
+
+impl Mapping {
+    fn parse(s: &str) -> Option<Mapping> {
+        match s {
+            "riblt" => Some(Mapping::Riblt),
+            "simulated" => Some(Mapping::Simulated),
+            _ => {
+                let alpha: f64 = s.strip_prefix("alpha=")?.parse().ok()?;
+                (alpha > 0.0).then_some(Mapping::Alpha(alpha))
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Mapping::Riblt => "riblt".to_string(),
+            Mapping::Simulated => "simulated".to_string(),
+            Mapping::Alpha(alpha) => format!("alpha={}", alpha),
+        }
+    }
+
+    // The number of coded symbols needed to decode the difference, or None if we gave up
+    fn coded_symbols_needed(&self, trial: u64, difference: usize) -> Option<usize> {
+        let max = MAX_CODED_SYMBOLS_PER_DIFFERENCE * difference.max(1);
+        match self {
+            Mapping::Riblt => decode(trial, difference, max),
+            Mapping::Simulated => simulate(trial, difference, max, |id| {
+                Box::new(RandomMapping::new(id))
+            }),
+            &Mapping::Alpha(alpha) => simulate(trial, difference, max, |id| {
+                Box::new(BernoulliMapping::new(id, alpha))
+            }),
+        }
+    }
+}
+
+// splitmix64
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// Distinct for every trial and symbol, the top bits keep the common symbols apart from the
+// difference
+fn id(trial: u64, kind: u64, i: u64) -> Id {
+    ByteSymbol(mix(trial).wrapping_add((kind << 62) | i).to_le_bytes())
+}
+
+// Half of the difference is local only, the other half remote only
+fn decode(trial: u64, difference: usize, max: usize) -> Option<usize> {
+    let local_only = (difference / 2) as u64;
+    let remote_only = (difference - difference / 2) as u64;
+    let common = (0..COMMON).map(|i| id(trial, 0, i));
+    let mut local = RatelessIBLT::new(
+        common
+            .clone()
+            .chain((0..local_only).map(|i| id(trial, 1, i)))
+            .collect::<Vec<Id>>(),
+    );
+    let mut remote = RatelessIBLT::new(
+        common
+            .chain((0..remote_only).map(|i| id(trial, 2, i)))
+            .collect::<Vec<Id>>(),
+    );
+
+    let mut decoder = Decoder::new();
+    while decoder.len() < max {
+        let index = decoder.len();
+        let remote_coded_symbol = remote.get_coded_symbol(index).unwrap();
+        let collapsed = local
+            .collapse_coded_symbol(index, &remote_coded_symbol)
+            .unwrap();
+        decoder.add_coded_symbol(&collapsed);
+        if decoder.is_empty() {
+            return Some(decoder.len());
+        }
+    }
+    None
+}
+
+// Peeling only needs to know which symbols land on each coded symbol. Each coded symbol keeps
+// the number of unpeeled symbols it holds and the XOR of their positions, so a coded symbol
+// holding just one tells us which.
+fn simulate<F>(trial: u64, difference: usize, max: usize, mapping: F) -> Option<usize>
+where
+    F: Fn(&Id) -> Box<dyn Iterator<Item = usize>>,
+{
+    let ids: Vec<Id> = (0..difference as u64).map(|i| id(trial, 1, i)).collect();
+    let mut mappings: Vec<_> = ids.iter().map(&mapping).collect();
+    // (next index, position), for the unpeeled symbols
+    let mut next_indexes: BinaryHeap<Reverse<(usize, usize)>> = mappings
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(position, m)| Some(Reverse((m.next()?, position))))
+        .collect();
+    let mut peeled = vec![false; difference];
+    let mut remaining = difference;
+    let mut degrees: Vec<usize> = Vec::new();
+    let mut positions: Vec<usize> = Vec::new();
+
+    while remaining > 0 {
+        let index = degrees.len();
+        if index == max {
+            return None;
+        }
+        let (mut degree, mut xor) = (0, 0);
+        while let Some(&Reverse((next, position))) = next_indexes.peek() {
+            if next != index {
+                break;
+            }
+            next_indexes.pop();
+            if peeled[position] {
+                continue;
+            }
+            degree += 1;
+            xor ^= position;
+            if let Some(next) = mappings[position].next() {
+                next_indexes.push(Reverse((next, position)));
+            }
+        }
+        degrees.push(degree);
+        positions.push(xor);
+
+        let mut candidates = vec![index];
+        while let Some(index) = candidates.pop() {
+            if degrees[index] != 1 {
+                continue;
+            }
+            let position = positions[index];
+            peeled[position] = true;
+            remaining -= 1;
+            let len = degrees.len();
+            for i in mapping(&ids[position]).take_while(|&i| i < len) {
+                degrees[i] -= 1;
+                positions[i] ^= position;
+                if degrees[i] == 1 {
+                    candidates.push(i);
+                }
+            }
+        }
+    }
+    Some(degrees.len())
+}
+
+// Maps to index i with probability 1 / (1 + alpha * i), seeded by the symbol's hash
+struct BernoulliMapping {
+    prng: u64,
+    index: usize,
+    alpha: f64,
+}
+
+impl BernoulliMapping {
+    fn new(symbol: &Id, alpha: f64) -> Self {
+        BernoulliMapping {
+            prng: symbol.hash_(),
+            index: 0,
+            alpha,
+        }
+    }
+}
+
+impl Iterator for BernoulliMapping {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let index = self.index;
+            self.index += 1;
+            self.prng = mix(self.prng);
+            let r = (self.prng >> 11) as f64 / (1u64 << 53) as f64;
+            if r < 1.0 / (1.0 + self.alpha * index as f64) {
+                return Some(index);
+            }
+        }
+    }
+}
+
+struct Stats {
+    trials: usize,
+    failed: usize,
+    mean: f64,
+    stddev: f64,
+    // min, p50, p90, p99, max
+    percentiles: [usize; 5],
+}
+
+impl Stats {
+    fn new(mut samples: Vec<usize>, trials: usize) -> Stats {
+        samples.sort_unstable();
+        let n = samples.len().max(1) as f64;
+        let mean = samples.iter().sum::<usize>() as f64 / n;
+        let variance = samples
+            .iter()
+            .map(|&s| (s as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let percentile = |p: f64| {
+            let rank = ((samples.len() as f64 * p).ceil() as usize).clamp(1, samples.len().max(1));
+            samples.get(rank - 1).copied().unwrap_or(0)
+        };
+        Stats {
+            trials,
+            failed: trials - samples.len(),
+            mean,
+            stddev: variance.sqrt(),
+            percentiles: [
+                percentile(0.0),
+                percentile(0.5),
+                percentile(0.9),
+                percentile(0.99),
+                percentile(1.0),
+            ],
+        }
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("usage: overhead [--trials N] [--mapping riblt|simulated|alpha=A,...] [d ...]");
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    let mut trials = 100;
+    let mut mappings = vec![Mapping::Riblt];
+    let mut differences = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trials" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => trials = n,
+                _ => return usage(),
+            },
+            "--mapping" => {
+                let Some(list) = args.next() else {
+                    return usage();
+                };
+                match list.split(',').map(Mapping::parse).collect() {
+                    Some(list) => mappings = list,
+                    None => return usage(),
+                }
+            }
+            d => match d.parse() {
+                Ok(d) if d > 0 => differences.push(d),
+                _ => return usage(),
+            },
+        }
+    }
+    if differences.is_empty() {
+        differences = vec![10, 100, 1_000];
+    }
+
+    println!(
+        "{:<12} {:>7} {:>7} {:>10} {:>9} {:>9} {:>7} {:>7} {:>7} {:>7} {:>7}",
+        "mapping", "d", "trials", "mean", "mean/d", "stddev", "min", "p50", "p90", "p99", "max"
+    );
+    for mapping in &mappings {
+        for &difference in &differences {
+            let samples = (0..trials as u64)
+                .filter_map(|trial| mapping.coded_symbols_needed(trial, difference))
+                .collect();
+            let stats = Stats::new(samples, trials);
+            let [min, p50, p90, p99, max] = stats.percentiles;
+            print!(
+                "{:<12} {:>7} {:>7} {:>10.1} {:>9.3} {:>9.1} {:>7} {:>7} {:>7} {:>7} {:>7}",
+                mapping.name(),
+                difference,
+                stats.trials,
+                stats.mean,
+                stats.mean / difference as f64,
+                stats.stddev,
+                min,
+                p50,
+                p90,
+                p99,
+                max
+            );
+            if stats.failed > 0 {
+                print!("  ({} trials gave up)", stats.failed);
+            }
+            println!();
+        }
+    }
+    ExitCode::SUCCESS
+}
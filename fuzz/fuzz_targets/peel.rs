@@ -0,0 +1,47 @@
+#![no_main]
+
+// Arbitrary bytes as coded symbols in the wire format, collapsed against a small local set the
+// way a receiver would. The first byte picks the size of the local set. Nothing may panic, and
+// peeling must stop after at most one peel per coded symbol, whether it's done from scratch or
+// streamed through a Decoder.
+
+use libfuzzer_sys::fuzz_target;
+use riblt::{
+    decode_coded_symbol, encoded_len, ByteSymbol, Decoder, RatelessIBLT, UnmanagedRatelessIBLT,
+};
+
+type Id = ByteSymbol<8>;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&local_len, data)) = data.split_first() else {
+        return;
+    };
+    let mut local = RatelessIBLT::new(
+        (0..local_len as u64)
+            .map(|value| ByteSymbol(value.to_le_bytes()))
+            .collect::<Vec<Id>>(),
+    );
+
+    let mut remote = UnmanagedRatelessIBLT::<Id>::new();
+    let mut decoder = Decoder::new();
+    for (index, bytes) in data.chunks(encoded_len::<Id>()).enumerate() {
+        let Ok(coded_symbol) = decode_coded_symbol::<Id>(bytes) else {
+            // only the last chunk can be short
+            assert_eq!(index, data.len() / encoded_len::<Id>());
+            break;
+        };
+        remote.add_coded_symbol(&coded_symbol);
+        let collapsed = local.collapse_coded_symbol(index, &coded_symbol).unwrap();
+        decoder.add_coded_symbol(&collapsed);
+    }
+
+    let len = remote.coded_symbols.len();
+    let mut collapsed = local.collapse(&remote).unwrap();
+    assert!(collapsed.peel_iter().count() <= len);
+    collapsed.decode_status();
+    assert!(decoder.recovered_so_far().len() <= len);
+    decoder.decode_status();
+
+    // peeling the remote's coded symbols on their own
+    assert!(remote.peel_all_symbols().len() <= len);
+});
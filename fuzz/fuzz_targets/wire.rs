@@ -0,0 +1,44 @@
+#![no_main]
+
+// Arbitrary bytes as a stream from a remote: a header, then coded symbols until the bytes run out.
+// Nothing may panic, and peeling whatever arrived must stop after at most one peel per coded
+// symbol.
+
+use libfuzzer_sys::fuzz_target;
+use riblt::{read_coded_symbol, ByteSymbol, Decoder, Header, UnmanagedRatelessIBLT};
+
+// The same kind of limit a real reader would have on how many coded symbols it accepts
+const MAX_CODED_SYMBOLS: usize = 4096;
+
+type Id = ByteSymbol<8>;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = data;
+    // Most inputs won't start with the magic, read the coded symbols from the start for those
+    match Header::read(&mut reader) {
+        Ok(header) => {
+            let _ = header.check::<Id>(header.cutoff);
+            if let Some(fingerprint) = header.fingerprint {
+                header.in_sync_with(&fingerprint);
+            }
+        }
+        Err(_) => reader = data,
+    }
+
+    let mut received = UnmanagedRatelessIBLT::<Id>::new();
+    let mut decoder = Decoder::<Id>::new();
+    while received.coded_symbols.len() < MAX_CODED_SYMBOLS {
+        let Ok(coded_symbol) = read_coded_symbol::<Id, _>(&mut reader) else {
+            break;
+        };
+        received.add_coded_symbol(&coded_symbol);
+        decoder.add_coded_symbol(&coded_symbol);
+    }
+
+    let len = received.coded_symbols.len();
+    assert!(received.peel_iter().count() <= len);
+    received.decode_status();
+    received.is_empty();
+    assert!(decoder.recovered_so_far().len() <= len);
+    decoder.decode_status();
+});
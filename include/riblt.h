@@ -0,0 +1,167 @@
+/* The C API of the riblt crate, built with the capi feature. */
+
+#ifndef RIBLT_H
+#define RIBLT_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Returned when the call succeeded
+#define RIBLT_OK 0
+
+// Returned when a handle or buffer is NULL, or a buffer is too small
+#define RIBLT_ERROR_INVALID_ARGUMENT -1
+
+// Returned when the set iterator's rewind or next callback reported an error
+#define RIBLT_ERROR_SET -2
+
+// Returned by riblt_decoder_next_difference when there are no more differences (yet)
+#define RIBLT_NO_DIFFERENCE 0
+
+// The symbol is in the local set, but not the remote set
+#define RIBLT_LOCAL 1
+
+// The symbol is in the remote set, but not the local set
+#define RIBLT_REMOTE 2
+
+// Peels differences out of collapsed coded symbols, create it with riblt_decoder_new
+typedef struct RibltDecoder RibltDecoder;
+
+// An encoder over a local set, create it with riblt_encoder_new or riblt_encoder_new_from_buffer
+typedef struct RibltEncoder RibltEncoder;
+
+// Callbacks that iterate over the set, the encoder iterates over it once per block of coded
+// symbols it generates.
+//
+// rewind goes back to the start of the set, and returns 0 on success.
+// next writes the next symbol (width bytes) to symbol and returns 1, returns 0 once every symbol
+// has been written, or a negative value on error.
+// Both are passed context, which is otherwise left alone.
+typedef struct RibltSetIterator {
+  void *context;
+  int (*rewind)(void *context);
+  int (*next)(void *context, uint8_t *symbol);
+} RibltSetIterator;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The number of bytes a coded symbol takes up for symbols of the width, or 0 if the width is
+// not supported. The supported widths are 4, 8, 16, 20, 32 and 64 bytes.
+size_t riblt_coded_symbol_len(size_t width);
+
+// Create an encoder over the set the iterator iterates over.
+//
+// Returns NULL if the width is not supported or either callback is NULL.
+// The set must not change while the encoder is in use.
+struct RibltEncoder *riblt_encoder_new(size_t width, struct RibltSetIterator iterator);
+
+// Create an encoder over a set that is already in memory, symbols holds len bytes: every
+// symbol one after the other. The symbols are copied, so the buffer can be freed afterwards.
+//
+// Returns NULL if the width is not supported or len is not a multiple of the width.
+//
+// # Safety
+//
+// symbols must point to len readable bytes.
+struct RibltEncoder *riblt_encoder_new_from_buffer(size_t width,
+                                                   const uint8_t *symbols,
+                                                   size_t len);
+
+// Write the coded symbol at index into out, to stream to a remote.
+//
+// # Safety
+//
+// encoder must come from riblt_encoder_new, and out must point to out_len writable bytes.
+int riblt_encoder_get_coded_symbol(struct RibltEncoder *encoder,
+                                   size_t index,
+                                   uint8_t *out,
+                                   size_t out_len);
+
+// Subtract a coded symbol received from a remote from our coded symbol at the same index, and
+// write the result into out, ready for riblt_decoder_add_coded_symbol.
+//
+// # Safety
+//
+// encoder must come from riblt_encoder_new, remote must point to remote_len readable bytes and
+// out must point to out_len writable bytes.
+int riblt_encoder_collapse_coded_symbol(struct RibltEncoder *encoder,
+                                        size_t index,
+                                        const uint8_t *remote,
+                                        size_t remote_len,
+                                        uint8_t *out,
+                                        size_t out_len);
+
+// Write the fingerprint of the set (see Fingerprint in the Rust docs) to hash and count.
+//
+// If it matches the remote's fingerprint, the sets are the same and there is no need to stream
+// any coded symbols.
+//
+// # Safety
+//
+// encoder must come from riblt_encoder_new, hash and count must be writable.
+int riblt_encoder_fingerprint(const struct RibltEncoder *encoder, uint64_t *hash, int64_t *count);
+
+// # Safety
+//
+// encoder must come from riblt_encoder_new (or be NULL), and not be used afterwards.
+void riblt_encoder_free(struct RibltEncoder *encoder);
+
+// Create a decoder for symbols of the width, returns NULL if the width is not supported.
+struct RibltDecoder *riblt_decoder_new(size_t width);
+
+// Add the next collapsed coded symbol (see riblt_encoder_collapse_coded_symbol) and peel
+// everything we can.
+//
+// # Safety
+//
+// decoder must come from riblt_decoder_new, and coded_symbol must point to len readable bytes.
+int riblt_decoder_add_coded_symbol(struct RibltDecoder *decoder,
+                                   const uint8_t *coded_symbol,
+                                   size_t len);
+
+// Write the next difference that has been peeled (width bytes) into out.
+//
+// Returns RIBLT_LOCAL or RIBLT_REMOTE, or RIBLT_NO_DIFFERENCE if every difference peeled so far
+// has already been returned. Each difference is only returned once.
+//
+// # Safety
+//
+// decoder must come from riblt_decoder_new, and out must point to out_len writable bytes.
+int riblt_decoder_next_difference(struct RibltDecoder *decoder, uint8_t *out, size_t out_len);
+
+// Write 1 to is_empty if every coded symbol added is empty, meaning the whole difference has
+// been peeled, or 0 if more coded symbols are needed.
+// Like Decoder::is_empty, this is also 1 before any coded symbols have been added.
+//
+// The result goes through is_empty so that an error can't be mistaken for it, the return value
+// is RIBLT_OK or an error.
+//
+// # Safety
+//
+// decoder must come from riblt_decoder_new, is_empty must be writable.
+int riblt_decoder_is_empty(const struct RibltDecoder *decoder, int *is_empty);
+
+// The number of coded symbols added, which is also the index of the next one to add.
+//
+// # Safety
+//
+// decoder must come from riblt_decoder_new.
+size_t riblt_decoder_len(const struct RibltDecoder *decoder);
+
+// # Safety
+//
+// decoder must come from riblt_decoder_new (or be NULL), and not be used afterwards.
+void riblt_decoder_free(struct RibltDecoder *decoder);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RIBLT_H */
@@ -0,0 +1,289 @@
+use crate::encoder::RatelessIBLT;
+use crate::error::Error;
+use crate::source::SymbolSource;
+use crate::symbol;
+use alloc::vec::Vec;
+
+/// How often the AntiEntropy takes a snapshot, and how long the previous snapshot is kept.
+///
+/// Times are in whatever unit the caller passes to poll_at (e.g. seconds since the unix epoch).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AntiEntropyConfig {
+    /// Time between snapshots
+    pub interval: u64,
+    /// How long the previous epoch stays available after a new one starts.
+    /// This should comfortably cover the time a reconciliation takes.
+    /// Only one previous epoch is kept, so a grace longer than the interval has no extra effect.
+    pub grace: u64,
+}
+
+struct Epoch<T, S>
+where
+    T: symbol::Symbol,
+    S: SymbolSource<Symbol = T>,
+{
+    id: u64,
+    riblt: RatelessIBLT<T, S>,
+}
+
+/// The periodic repair mechanism from the README's "Challenges for rapidly changing sets".
+///
+/// Every interval, the snapshot function is called to produce a fixed view of the set and a new
+/// RatelessIBLT is built over it. This is an 'epoch', identified by an increasing id.
+/// Peers stream the coded symbols of a particular epoch (get_coded_symbol), so a reconciliation
+/// that is in progress when a new epoch starts can carry on against the previous epoch until the
+/// grace period runs out.
+///
+/// There are no background threads or timers, the calling code is expected to call poll_at
+/// regularly (at least once per interval) with the current time.
+///
+/// The snapshot function is passed the time of the poll, which makes it easy to only include
+/// settled symbols, e.g. `|now| Windowed::new(set.clone(), now - 10)`.
+///
+/// The snapshot must be a fixed view that the caller won't change for the life of the epoch.
+/// poll_at generates the first block straight away, so the epoch's fingerprint is taken at the
+/// boundary, but every later block scans the source again. A source that reads something still
+/// being written to (e.g. a FileSymbolSource over the live file) would give later blocks from a
+/// different set than earlier ones, and peers would fail to decode. Take an owned copy instead,
+/// e.g. clone the Vec or copy the file.
+pub struct AntiEntropy<T, S, F>
+where
+    T: symbol::Symbol,
+    S: SymbolSource<Symbol = T>,
+    F: FnMut(u64) -> S,
+{
+    config: AntiEntropyConfig,
+    snapshot: F,
+    current: Option<Epoch<T, S>>,
+    // the previous epoch, and when it expires
+    previous: Option<(Epoch<T, S>, u64)>,
+    next_snapshot_at: u64,
+    next_id: u64,
+}
+
+impl<T, S, F> AntiEntropy<T, S, F>
+where
+    T: symbol::Symbol,
+    S: SymbolSource<Symbol = T>,
+    F: FnMut(u64) -> S,
+{
+    /// No snapshot is taken until the first call to poll_at
+    pub fn new(config: AntiEntropyConfig, snapshot: F) -> Self {
+        AntiEntropy {
+            config,
+            snapshot,
+            current: None,
+            previous: None,
+            next_snapshot_at: 0,
+            next_id: 0,
+        }
+    }
+
+    /// Start a new epoch if the interval has passed, and drop the previous epoch if its grace
+    /// period has run out.
+    ///
+    /// Returns the id of the new epoch if one was started.
+    ///
+    /// The first block of the new epoch is generated here. If scanning the snapshot fails, the
+    /// error is returned and no epoch is started, so the next poll tries again.
+    pub fn poll_at(&mut self, now: u64) -> Result<Option<u64>, Error> {
+        if matches!(self.previous, Some((_, expires_at)) if now >= expires_at) {
+            self.previous = None;
+        }
+        if self.current.is_some() && now < self.next_snapshot_at {
+            return Ok(None);
+        }
+
+        let id = self.next_id;
+        let mut riblt = RatelessIBLT::new((self.snapshot)(now));
+        riblt.extend_coded_symbols(0)?;
+        let epoch = Epoch { id, riblt };
+        if let Some(current) = self.current.replace(epoch) {
+            self.previous = Some((current, now.saturating_add(self.config.grace)));
+        }
+        self.next_id += 1;
+        self.next_snapshot_at = now.saturating_add(self.config.interval);
+        Ok(Some(id))
+    }
+
+    /// The id of the newest epoch, this is the one new reconciliations should use.
+    pub fn current_epoch(&self) -> Option<u64> {
+        self.current.as_ref().map(|epoch| epoch.id)
+    }
+
+    /// The ids of every epoch peers can request coded symbols for at the provided time, newest
+    /// first
+    pub fn available_epochs(&self, now: u64) -> Vec<u64> {
+        let previous = self
+            .previous
+            .iter()
+            .filter(|(_, expires_at)| now < *expires_at)
+            .map(|(epoch, _)| epoch);
+        self.current
+            .iter()
+            .chain(previous)
+            .map(|epoch| epoch.id)
+            .collect()
+    }
+
+    /// Returns the coded symbol at the provided index, for the provided epoch.
+    ///
+    /// Returns Error::UnknownEpoch if the epoch has expired by now (or hasn't started yet), the
+    /// remote should start again with the current epoch.
+    pub fn get_coded_symbol(
+        &mut self,
+        epoch: u64,
+        index: usize,
+        now: u64,
+    ) -> Result<symbol::CodedSymbol<T>, Error> {
+        self.riblt(epoch, now)?.get_coded_symbol(index)
+    }
+
+    /// The RatelessIBLT for the provided epoch, e.g. to collapse against a remote's coded symbols
+    ///
+    /// The previous epoch's grace period is checked against now, so it isn't served after it
+    /// expires even if poll_at hasn't been called since.
+    pub fn riblt(&mut self, epoch: u64, now: u64) -> Result<&mut RatelessIBLT<T, S>, Error> {
+        let previous = self
+            .previous
+            .as_mut()
+            .filter(|(_, expires_at)| now < *expires_at)
+            .map(|(epoch, _)| epoch);
+        self.current
+            .as_mut()
+            .into_iter()
+            .chain(previous)
+            .find(|e| e.id == epoch)
+            .map(|e| &mut e.riblt)
+            .ok_or(Error::UnknownEpoch { epoch })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::SimpleSymbol;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_epochs() {
+        // An insert only set, growing by one symbol per time unit
+        let set = RefCell::new(Vec::new());
+        let config = AntiEntropyConfig {
+            interval: 60,
+            grace: 15,
+        };
+        let mut anti_entropy = AntiEntropy::new(config, |_now| set.borrow().clone());
+        assert_eq!(anti_entropy.current_epoch(), None);
+        assert!(matches!(
+            anti_entropy.get_coded_symbol(0, 0, 0),
+            Err(Error::UnknownEpoch { epoch: 0 })
+        ));
+
+        for now in 0..200u64 {
+            set.borrow_mut().push(SimpleSymbol { value: now });
+            let started = anti_entropy.poll_at(now).unwrap();
+            match now {
+                0 => assert_eq!(started, Some(0)),
+                60 => assert_eq!(started, Some(1)),
+                120 => assert_eq!(started, Some(2)),
+                180 => assert_eq!(started, Some(3)),
+                _ => assert_eq!(started, None),
+            }
+
+            let expected = match now {
+                0..=59 => vec![0],
+                60..=74 => vec![1, 0],
+                75..=119 => vec![1],
+                120..=134 => vec![2, 1],
+                135..=179 => vec![2],
+                180..=194 => vec![3, 2],
+                _ => vec![3],
+            };
+            assert_eq!(anti_entropy.available_epochs(now), expected);
+        }
+
+        // Epoch 3 was snapshotted at time 180, when the set held 181 symbols
+        assert_eq!(anti_entropy.get_coded_symbol(3, 0, 200).unwrap().count, 181);
+        assert!(matches!(
+            anti_entropy.get_coded_symbol(2, 0, 200),
+            Err(Error::UnknownEpoch { epoch: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_in_flight_reconciliation() {
+        let set = RefCell::new((0..100).map(|value| SimpleSymbol { value }).collect::<Vec<_>>());
+        let config = AntiEntropyConfig {
+            interval: 10,
+            grace: 5,
+        };
+        let mut anti_entropy = AntiEntropy::new(config, |_now| set.borrow().clone());
+        let epoch = anti_entropy.poll_at(0).unwrap().unwrap();
+        let first = anti_entropy.get_coded_symbol(epoch, 3, 0).unwrap();
+
+        // The set changes and a new epoch starts part way through streaming
+        set.borrow_mut().push(SimpleSymbol { value: 100 });
+        assert_eq!(anti_entropy.poll_at(10).unwrap(), Some(epoch + 1));
+
+        // The remote carries on with the epoch it started with, and gets consistent coded symbols
+        let again = anti_entropy.get_coded_symbol(epoch, 3, 12).unwrap();
+        assert_eq!(again.sum, first.sum);
+        let previous = anti_entropy.get_coded_symbol(epoch, 0, 12).unwrap();
+        let current = anti_entropy.get_coded_symbol(epoch + 1, 0, 12).unwrap();
+        assert_eq!(previous.count, 100);
+        assert_eq!(current.count, 101);
+
+        anti_entropy.poll_at(15).unwrap();
+        assert!(anti_entropy.get_coded_symbol(epoch, 0, 15).is_err());
+    }
+
+    #[test]
+    fn test_expires_without_poll() {
+        let config = AntiEntropyConfig {
+            interval: 10,
+            grace: 5,
+        };
+        let mut anti_entropy = AntiEntropy::new(config, |now| vec![SimpleSymbol { value: now }]);
+        anti_entropy.poll_at(0).unwrap();
+        anti_entropy.poll_at(10).unwrap();
+        assert!(anti_entropy.get_coded_symbol(0, 0, 14).is_ok());
+        assert_eq!(anti_entropy.available_epochs(14), vec![1, 0]);
+
+        // No poll since the grace period ran out, the previous epoch still isn't served
+        assert!(matches!(
+            anti_entropy.get_coded_symbol(0, 0, 15),
+            Err(Error::UnknownEpoch { epoch: 0 })
+        ));
+        assert_eq!(anti_entropy.available_epochs(15), vec![1]);
+        assert!(anti_entropy.riblt(1, 15).is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_snapshot_at_poll() {
+        // The first block is generated by poll_at, so changes to the set after the epoch starts
+        // don't reach it, even for a source that is rescanned for every block
+        let dir = std::env::temp_dir().join(format!("riblt-anti-entropy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("set");
+        let _ = std::fs::remove_file(&path);
+        let file = crate::FileSymbolSource::<SimpleSymbol>::new(&path);
+        file.append(&[SimpleSymbol { value: 1 }, SimpleSymbol { value: 2 }])
+            .unwrap();
+
+        let config = AntiEntropyConfig {
+            interval: 10,
+            grace: 5,
+        };
+        let mut anti_entropy = AntiEntropy::new(config, |_now| file.clone());
+        let epoch = anti_entropy.poll_at(0).unwrap().unwrap();
+        file.append(&[SimpleSymbol { value: 3 }]).unwrap();
+
+        assert_eq!(anti_entropy.get_coded_symbol(epoch, 0, 1).unwrap().count, 2);
+        let fingerprint = anti_entropy.riblt(epoch, 1).unwrap().fingerprint().unwrap();
+        assert_eq!(fingerprint.count, 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
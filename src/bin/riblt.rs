@@ -0,0 +1,334 @@
+//! riblt: find the difference between two files of lines, without having both files in one place.
+//!
+//! Each line is hashed to a SHA-256 digest, so duplicate lines count once and the order of the
+//! lines doesn't matter.
+//!
+//! ```text
+//! riblt encode <file> --count N          write N coded symbols of the file to stdout
+//! riblt diff <local-file> <stream>       print the lines only in the local file (-) and the
+//!                                        digests of the lines only in the remote file (+)
+//! riblt stats <local-file> <stream>      how many coded symbols the difference needed
+//! riblt serve <file>                     stream coded symbols of the file to stdout, until the
+//!                                        reader hangs up
+//! riblt sync <local-file>                read coded symbols from stdin until the difference is
+//!                                        found, and print it like diff
+//! ```
+//!
+//! Use - for the stream to read it from stdin, e.g.
+//! `ssh host riblt encode ids.txt --count 1000 | riblt diff ids.txt -`
+//!
+//! serve and sync don't need to know the size of the difference up front, sync stops reading as
+//! soon as it has peeled everything, which in turn stops serve:
+//! `ssh host riblt serve ids.txt | riblt sync ids.txt`
+//! The pipe only goes one way, so lines only the remote has are printed as their digests.
+//!
+//! diff and sync exit with 0 if the files are the same, 1 if they differ and 2 if something went
+//! wrong (including the stream being too short to find the whole difference).
+
+use riblt::{
+    read_coded_symbol, write_coded_symbol, CodedSymbol, Decoder, Difference, Error, Header,
+    RatelessIBLT, Symbol, UnmanagedRatelessIBLT,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::process::ExitCode;
+
+// sync gives up after this many coded symbols per line (local and remote), plus a little extra
+// for small files. This is far more than any difference needs, it stops a misbehaving remote
+// from streaming forever.
+const MAX_CODED_SYMBOLS_PER_LINE: usize = 4;
+const MIN_CODED_SYMBOLS: usize = 1024;
+
+const USAGE: &str = "usage:
+    riblt encode <file> --count N
+    riblt diff <local-file> <stream>
+    riblt stats <local-file> <stream>
+    riblt serve <file>
+    riblt sync <local-file>";
+
+/// The SHA-256 digest of a line
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct LineDigest([u8; 32]);
+
+impl Symbol for LineDigest {
+    const BYTE_ARRAY_LENGTH: usize = 32;
+
+    fn encode_to_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    fn decode_from_bytes(bytes: &Vec<u8>) -> Self {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(bytes);
+        LineDigest(digest)
+    }
+}
+
+impl LineDigest {
+    fn hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+// Every distinct line in the file, by digest so we can print the local lines we peel
+fn read_lines(path: &str) -> io::Result<HashMap<LineDigest, String>> {
+    let mut lines = HashMap::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let digest = LineDigest(Sha256::digest(line.as_bytes()).into());
+        lines.insert(digest, line);
+    }
+    Ok(lines)
+}
+
+fn open_stream(path: &str) -> io::Result<Box<dyn Read>> {
+    if path == "-" {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+// Read the next coded symbol, or None at the end of the stream. The stream can only end between
+// coded symbols, if it ends part way through one it was truncated.
+fn read_next_coded_symbol<R: Read>(
+    reader: &mut R,
+) -> Result<Option<CodedSymbol<LineDigest>>, Error> {
+    let mut first = [0u8; 1];
+    loop {
+        match reader.read(&mut first) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    match read_coded_symbol(&mut first.as_slice().chain(reader)) {
+        Ok(coded_symbol) => Ok(Some(coded_symbol)),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(Error::InvalidFormat(
+            "the stream ends part way through a coded symbol",
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Read coded symbols until the end of the stream
+fn read_coded_symbols<R: Read>(reader: &mut R) -> Result<UnmanagedRatelessIBLT<LineDigest>, Error> {
+    let mut remote = UnmanagedRatelessIBLT::new();
+    while let Some(coded_symbol) = read_next_coded_symbol(reader)? {
+        remote.add_coded_symbol(&coded_symbol);
+    }
+    Ok(remote)
+}
+
+fn encode(path: &str, count: usize) -> Result<ExitCode, Error> {
+    let lines = read_lines(path)?;
+    let mut iblt = RatelessIBLT::new(lines.keys().cloned());
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    Header::new::<LineDigest>()
+        .with_fingerprint(iblt.fingerprint()?)
+        .write(&mut writer)?;
+    for index in 0..count {
+        write_coded_symbol(&mut writer, &iblt.get_coded_symbol(index)?)?;
+    }
+    writer.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn diff(local_path: &str, stream_path: &str) -> Result<ExitCode, Error> {
+    let lines = read_lines(local_path)?;
+    let mut iblt = RatelessIBLT::new(lines.keys().cloned());
+
+    let mut reader = open_stream(stream_path)?;
+    let header = Header::read(&mut reader)?;
+    header.check::<LineDigest>(None)?;
+    if header.in_sync_with(&iblt.fingerprint()?) {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let remote = read_coded_symbols(&mut reader)?;
+    let mut collapsed = iblt.collapse(&remote)?;
+    let differences: Vec<_> = collapsed.peel_iter().collect();
+    print_differences(&lines, differences.iter().cloned())?;
+
+    // The fingerprints differ, so there is a difference to find. Finding none means the stream
+    // stopped too soon, e.g. it was encoded with --count 0.
+    let nothing_found = differences.is_empty() && header.fingerprint.is_some();
+    if !collapsed.is_empty() || nothing_found {
+        eprintln!(
+            "riblt: {} coded symbols were not enough to find the whole difference, encode more with --count",
+            remote.coded_symbols.len()
+        );
+        return Ok(ExitCode::from(2));
+    }
+    Ok(ExitCode::from(1))
+}
+
+// A forged or corrupt stream can peel a local digest that isn't in the file, that's an error
+// rather than a panic
+fn print_differences(
+    lines: &HashMap<LineDigest, String>,
+    differences: impl IntoIterator<Item = Difference<LineDigest>>,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(io::stdout().lock());
+    for difference in differences {
+        match difference {
+            Difference::Local(digest) => {
+                let line = lines.get(&digest).ok_or(Error::InvalidFormat(
+                    "the coded symbols are inconsistent with the local file",
+                ))?;
+                writeln!(writer, "- {}", line)?;
+            }
+            Difference::Remote(digest) => writeln!(writer, "+ {}", digest.hex())?,
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn serve(path: &str) -> Result<ExitCode, Error> {
+    let lines = read_lines(path)?;
+    let mut iblt = RatelessIBLT::new(lines.keys().cloned());
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    let header = Header::new::<LineDigest>().with_fingerprint(iblt.fingerprint()?);
+    let mut result = header.write(&mut writer);
+    let mut index = 0;
+    while result.is_ok() {
+        result = iblt
+            .get_coded_symbol(index)
+            .and_then(|coded_symbol| Ok(write_coded_symbol(&mut writer, &coded_symbol)?));
+        index += 1;
+    }
+
+    match result {
+        // The reader has everything it needs and hung up
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => Ok(ExitCode::SUCCESS),
+        Err(err) => Err(err),
+        Ok(()) => unreachable!(),
+    }
+}
+
+fn sync(local_path: &str) -> Result<ExitCode, Error> {
+    let lines = read_lines(local_path)?;
+    let mut iblt = RatelessIBLT::new(lines.keys().cloned());
+
+    let mut reader = BufReader::new(io::stdin().lock());
+    let header = Header::read(&mut reader)?;
+    header.check::<LineDigest>(None)?;
+    if header.in_sync_with(&iblt.fingerprint()?) {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let remote_lines = header.fingerprint.map_or(0, |f| f.count.max(0) as usize);
+    // the remote's count is untrusted, don't let it overflow
+    let max_coded_symbols = MAX_CODED_SYMBOLS_PER_LINE
+        .saturating_mul(lines.len().saturating_add(remote_lines))
+        .saturating_add(MIN_CODED_SYMBOLS);
+    let mut decoder = Decoder::new();
+    loop {
+        if decoder.len() == max_coded_symbols {
+            eprintln!(
+                "riblt: gave up after {} coded symbols, the coded symbols are not from the same kind of file",
+                max_coded_symbols
+            );
+            return Ok(ExitCode::from(2));
+        }
+        let Some(coded_symbol) = read_next_coded_symbol(&mut reader)? else {
+            print_differences(&lines, decoder.recovered_so_far().iter().cloned())?;
+            eprintln!(
+                "riblt: the stream ended after {} coded symbols, before the whole difference was found",
+                decoder.len()
+            );
+            return Ok(ExitCode::from(2));
+        };
+        decoder.add_coded_symbol(&iblt.collapse_coded_symbol(decoder.len(), &coded_symbol)?);
+        if decoder.is_empty() {
+            break;
+        }
+    }
+
+    print_differences(&lines, decoder.recovered_so_far().iter().cloned())?;
+    Ok(ExitCode::from(1))
+}
+
+fn stats(local_path: &str, stream_path: &str) -> Result<ExitCode, Error> {
+    let lines = read_lines(local_path)?;
+    let mut iblt = RatelessIBLT::new(lines.keys().cloned());
+
+    let mut reader = open_stream(stream_path)?;
+    let header = Header::read(&mut reader)?;
+    header.check::<LineDigest>(None)?;
+    let remote = read_coded_symbols(&mut reader)?;
+
+    // Feed the coded symbols in one at a time, to find how many were actually needed
+    let mut decoder = Decoder::new();
+    let mut needed = None;
+    for (index, coded_symbol) in remote.coded_symbols.iter().enumerate() {
+        decoder.add_coded_symbol(&iblt.collapse_coded_symbol(index, &coded_symbol)?);
+        if decoder.is_empty() {
+            needed = Some(index + 1);
+            break;
+        }
+    }
+    let (local_only, remote_only) =
+        riblt::split_differences(decoder.recovered_so_far().iter().cloned());
+
+    println!("local lines: {}", lines.len());
+    if let Some(fingerprint) = header.fingerprint {
+        println!("remote lines: {}", fingerprint.count);
+    }
+    println!("coded symbols in stream: {}", remote.coded_symbols.len());
+    println!("local only: {}", local_only.len());
+    println!("remote only: {}", remote_only.len());
+    match needed {
+        Some(needed) => {
+            println!("coded symbols needed: {}", needed);
+            let difference = local_only.len() + remote_only.len();
+            if difference > 0 {
+                println!("overhead: {:.2}", needed as f64 / difference as f64);
+            }
+        }
+        None => println!(
+            "coded symbols needed: more than {}",
+            remote.coded_symbols.len()
+        ),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(args: &[String]) -> Result<ExitCode, Error> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["encode", path, "--count", count] | ["encode", "--count", count, path] => {
+            let Ok(count) = count.parse() else {
+                return Ok(usage());
+            };
+            encode(path, count)
+        }
+        ["diff", local, stream] => diff(local, stream),
+        ["stats", local, stream] => stats(local, stream),
+        ["serve", path] => serve(path),
+        ["sync", local] => sync(local),
+        _ => Ok(usage()),
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("riblt: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! A C ABI over opaque handles, for embedding in services that aren't written in Rust.
+//!
+//! Symbols are byte strings of a fixed width, chosen when the encoder or decoder is created.
+//! Coded symbols cross the boundary in the wire format (see write_coded_symbol), in buffers of
+//! riblt_coded_symbol_len(width) bytes.
+//!
+//! The header is include/riblt.h, generated by cbindgen (see cbindgen.toml). Regenerate it with
+//! `cbindgen --output include/riblt.h` after changing anything in this file.
+
+use crate::dynamic::{
+    coded_symbol_len, AnyDecoder, AnyEncoder, ConcatenatedSymbols, SourceFamily, SUPPORTED_WIDTHS,
+};
+use crate::error::Error;
+use crate::source::SymbolSource;
+use crate::symbol::{ByteSymbol, Fingerprint};
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::slice;
+
+/// Returned when the call succeeded
+pub const RIBLT_OK: c_int = 0;
+/// Returned when a handle or buffer is NULL, or a buffer is too small
+pub const RIBLT_ERROR_INVALID_ARGUMENT: c_int = -1;
+/// Returned when the set iterator's rewind or next callback reported an error
+pub const RIBLT_ERROR_SET: c_int = -2;
+
+/// Returned by riblt_decoder_next_difference when there are no more differences (yet)
+pub const RIBLT_NO_DIFFERENCE: c_int = 0;
+/// The symbol is in the local set, but not the remote set
+pub const RIBLT_LOCAL: c_int = 1;
+/// The symbol is in the remote set, but not the local set
+pub const RIBLT_REMOTE: c_int = 2;
+
+/// Callbacks that iterate over the set, the encoder iterates over it once per block of coded
+/// symbols it generates.
+///
+/// rewind goes back to the start of the set, and returns 0 on success.
+/// next writes the next symbol (width bytes) to symbol and returns 1, returns 0 once every symbol
+/// has been written, or a negative value on error.
+/// Both are passed context, which is otherwise left alone.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RibltSetIterator {
+    pub context: *mut c_void,
+    pub rewind: Option<unsafe extern "C" fn(context: *mut c_void) -> c_int>,
+    pub next: Option<unsafe extern "C" fn(context: *mut c_void, symbol: *mut u8) -> c_int>,
+}
+
+#[derive(Debug)]
+pub(crate) struct CallbackError(c_int);
+
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the set iterator returned {}", self.0)
+    }
+}
+
+impl std::error::Error for CallbackError {}
+
+pub(crate) struct CallbackSource<const N: usize> {
+    iterator: RibltSetIterator,
+}
+
+impl<const N: usize> SymbolSource for CallbackSource<N> {
+    type Symbol = ByteSymbol<N>;
+    type Error = CallbackError;
+
+    fn scan(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Self::Symbol, Self::Error>>, Self::Error> {
+        let RibltSetIterator {
+            context,
+            rewind: Some(rewind),
+            next: Some(next),
+        } = self.iterator
+        else {
+            unreachable!("checked by riblt_encoder_new");
+        };
+
+        let result = unsafe { rewind(context) };
+        if result != 0 {
+            return Err(CallbackError(result));
+        }
+
+        let mut done = false;
+        Ok(std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let mut symbol = [0u8; N];
+            match unsafe { next(context, symbol.as_mut_ptr()) } {
+                1 => Some(Ok(ByteSymbol(symbol))),
+                result => {
+                    done = true;
+                    (result != 0).then_some(Err(CallbackError(result)))
+                }
+            }
+        }))
+    }
+}
+
+impl SourceFamily for RibltSetIterator {
+    type Source<const N: usize> = CallbackSource<N>;
+
+    fn source<const N: usize>(self) -> CallbackSource<N> {
+        CallbackSource { iterator: self }
+    }
+}
+
+// The set is either read through callbacks, or copied in up front
+enum EncoderSet {
+    Callbacks(AnyEncoder<RibltSetIterator>),
+    Copied(AnyEncoder<ConcatenatedSymbols>),
+}
+
+/// An encoder over a local set, create it with riblt_encoder_new or riblt_encoder_new_from_buffer
+pub struct RibltEncoder(EncoderSet);
+
+impl RibltEncoder {
+    fn get_coded_symbol(&mut self, index: usize) -> Result<Vec<u8>, Error> {
+        match &mut self.0 {
+            EncoderSet::Callbacks(encoder) => encoder.get_coded_symbol(index),
+            EncoderSet::Copied(encoder) => encoder.get_coded_symbol(index),
+        }
+    }
+
+    fn collapse_coded_symbol(&mut self, index: usize, remote: &[u8]) -> Result<Vec<u8>, Error> {
+        match &mut self.0 {
+            EncoderSet::Callbacks(encoder) => encoder.collapse_coded_symbol(index, remote),
+            EncoderSet::Copied(encoder) => encoder.collapse_coded_symbol(index, remote),
+        }
+    }
+
+    fn fingerprint(&self) -> Result<Fingerprint, Error> {
+        match &self.0 {
+            EncoderSet::Callbacks(encoder) => encoder.fingerprint(),
+            EncoderSet::Copied(encoder) => encoder.fingerprint(),
+        }
+    }
+}
+
+/// Peels differences out of collapsed coded symbols, create it with riblt_decoder_new
+pub struct RibltDecoder(AnyDecoder);
+
+fn error_code(err: Error) -> c_int {
+    match err {
+        Error::Source(_) => RIBLT_ERROR_SET,
+        _ => RIBLT_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+// Copy a coded symbol into the caller's buffer
+fn write_to_buffer(result: Result<Vec<u8>, Error>, out: &mut [u8]) -> c_int {
+    match result {
+        Ok(coded_symbol) if coded_symbol.len() <= out.len() => {
+            out[..coded_symbol.len()].copy_from_slice(&coded_symbol);
+            RIBLT_OK
+        }
+        Ok(_) => RIBLT_ERROR_INVALID_ARGUMENT,
+        Err(err) => error_code(err),
+    }
+}
+
+/// The number of bytes a coded symbol takes up for symbols of the width, or 0 if the width is
+/// not supported. The supported widths are 4, 8, 16, 20, 32 and 64 bytes.
+#[no_mangle]
+pub extern "C" fn riblt_coded_symbol_len(width: usize) -> usize {
+    if SUPPORTED_WIDTHS.contains(&width) {
+        coded_symbol_len(width)
+    } else {
+        0
+    }
+}
+
+/// Create an encoder over the set the iterator iterates over.
+///
+/// Returns NULL if the width is not supported or either callback is NULL.
+/// The set must not change while the encoder is in use.
+#[no_mangle]
+pub extern "C" fn riblt_encoder_new(
+    width: usize,
+    iterator: RibltSetIterator,
+) -> *mut RibltEncoder {
+    if iterator.rewind.is_none() || iterator.next.is_none() {
+        return std::ptr::null_mut();
+    }
+    match AnyEncoder::new(width, iterator) {
+        Some(encoder) => Box::into_raw(Box::new(RibltEncoder(EncoderSet::Callbacks(encoder)))),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Create an encoder over a set that is already in memory, symbols holds len bytes: every
+/// symbol one after the other. The symbols are copied, so the buffer can be freed afterwards.
+///
+/// Returns NULL if the width is not supported or len is not a multiple of the width.
+///
+/// # Safety
+///
+/// symbols must point to len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_encoder_new_from_buffer(
+    width: usize,
+    symbols: *const u8,
+    len: usize,
+) -> *mut RibltEncoder {
+    if symbols.is_null() || width == 0 || !len.is_multiple_of(width) {
+        return std::ptr::null_mut();
+    }
+    let symbols = ConcatenatedSymbols(slice::from_raw_parts(symbols, len).to_vec());
+    match AnyEncoder::new(width, symbols) {
+        Some(encoder) => Box::into_raw(Box::new(RibltEncoder(EncoderSet::Copied(encoder)))),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Write the coded symbol at index into out, to stream to a remote.
+///
+/// # Safety
+///
+/// encoder must come from riblt_encoder_new, and out must point to out_len writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_encoder_get_coded_symbol(
+    encoder: *mut RibltEncoder,
+    index: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> c_int {
+    if encoder.is_null() || out.is_null() {
+        return RIBLT_ERROR_INVALID_ARGUMENT;
+    }
+    let out = slice::from_raw_parts_mut(out, out_len);
+    write_to_buffer((*encoder).get_coded_symbol(index), out)
+}
+
+/// Subtract a coded symbol received from a remote from our coded symbol at the same index, and
+/// write the result into out, ready for riblt_decoder_add_coded_symbol.
+///
+/// # Safety
+///
+/// encoder must come from riblt_encoder_new, remote must point to remote_len readable bytes and
+/// out must point to out_len writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_encoder_collapse_coded_symbol(
+    encoder: *mut RibltEncoder,
+    index: usize,
+    remote: *const u8,
+    remote_len: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> c_int {
+    if encoder.is_null() || remote.is_null() || out.is_null() {
+        return RIBLT_ERROR_INVALID_ARGUMENT;
+    }
+    let remote = slice::from_raw_parts(remote, remote_len);
+    let out = slice::from_raw_parts_mut(out, out_len);
+    write_to_buffer((*encoder).collapse_coded_symbol(index, remote), out)
+}
+
+/// Write the fingerprint of the set (see Fingerprint in the Rust docs) to hash and count.
+///
+/// If it matches the remote's fingerprint, the sets are the same and there is no need to stream
+/// any coded symbols.
+///
+/// # Safety
+///
+/// encoder must come from riblt_encoder_new, hash and count must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_encoder_fingerprint(
+    encoder: *const RibltEncoder,
+    hash: *mut u64,
+    count: *mut i64,
+) -> c_int {
+    if encoder.is_null() || hash.is_null() || count.is_null() {
+        return RIBLT_ERROR_INVALID_ARGUMENT;
+    }
+    match (*encoder).fingerprint() {
+        Ok(fingerprint) => {
+            *hash = fingerprint.hash;
+            *count = fingerprint.count;
+            RIBLT_OK
+        }
+        Err(err) => error_code(err),
+    }
+}
+
+/// # Safety
+///
+/// encoder must come from riblt_encoder_new (or be NULL), and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_encoder_free(encoder: *mut RibltEncoder) {
+    if !encoder.is_null() {
+        drop(Box::from_raw(encoder));
+    }
+}
+
+/// Create a decoder for symbols of the width, returns NULL if the width is not supported.
+#[no_mangle]
+pub extern "C" fn riblt_decoder_new(width: usize) -> *mut RibltDecoder {
+    match AnyDecoder::new(width) {
+        Some(decoder) => Box::into_raw(Box::new(RibltDecoder(decoder))),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Add the next collapsed coded symbol (see riblt_encoder_collapse_coded_symbol) and peel
+/// everything we can.
+///
+/// # Safety
+///
+/// decoder must come from riblt_decoder_new, and coded_symbol must point to len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_decoder_add_coded_symbol(
+    decoder: *mut RibltDecoder,
+    coded_symbol: *const u8,
+    len: usize,
+) -> c_int {
+    if decoder.is_null() || coded_symbol.is_null() {
+        return RIBLT_ERROR_INVALID_ARGUMENT;
+    }
+    let coded_symbol = slice::from_raw_parts(coded_symbol, len);
+    match (*decoder).0.add_coded_symbol(coded_symbol) {
+        Ok(()) => RIBLT_OK,
+        Err(err) => error_code(err),
+    }
+}
+
+/// Write the next difference that has been peeled (width bytes) into out.
+///
+/// Returns RIBLT_LOCAL or RIBLT_REMOTE, or RIBLT_NO_DIFFERENCE if every difference peeled so far
+/// has already been returned. Each difference is only returned once.
+///
+/// # Safety
+///
+/// decoder must come from riblt_decoder_new, and out must point to out_len writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_decoder_next_difference(
+    decoder: *mut RibltDecoder,
+    out: *mut u8,
+    out_len: usize,
+) -> c_int {
+    if decoder.is_null() || out.is_null() || out_len < (*decoder).0.width() {
+        return RIBLT_ERROR_INVALID_ARGUMENT;
+    }
+    let out = slice::from_raw_parts_mut(out, out_len);
+    match (*decoder).0.next_difference() {
+        Some(difference) => {
+            out[..difference.symbol.len()].copy_from_slice(&difference.symbol);
+            if difference.local {
+                RIBLT_LOCAL
+            } else {
+                RIBLT_REMOTE
+            }
+        }
+        None => RIBLT_NO_DIFFERENCE,
+    }
+}
+
+/// Write 1 to is_empty if every coded symbol added is empty, meaning the whole difference has
+/// been peeled, or 0 if more coded symbols are needed.
+/// Like Decoder::is_empty, this is also 1 before any coded symbols have been added.
+///
+/// The result goes through is_empty so that an error can't be mistaken for it, the return value
+/// is RIBLT_OK or an error.
+///
+/// # Safety
+///
+/// decoder must come from riblt_decoder_new, is_empty must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_decoder_is_empty(
+    decoder: *const RibltDecoder,
+    is_empty: *mut c_int,
+) -> c_int {
+    if decoder.is_null() || is_empty.is_null() {
+        return RIBLT_ERROR_INVALID_ARGUMENT;
+    }
+    *is_empty = (*decoder).0.is_empty() as c_int;
+    RIBLT_OK
+}
+
+/// The number of coded symbols added, which is also the index of the next one to add.
+///
+/// # Safety
+///
+/// decoder must come from riblt_decoder_new.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_decoder_len(decoder: *const RibltDecoder) -> usize {
+    if decoder.is_null() {
+        return 0;
+    }
+    (*decoder).0.len()
+}
+
+/// # Safety
+///
+/// decoder must come from riblt_decoder_new (or be NULL), and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn riblt_decoder_free(decoder: *mut RibltDecoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
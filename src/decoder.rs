@@ -0,0 +1,281 @@
+use crate::encoder;
+use crate::mapping;
+use crate::symbol;
+use crate::table::CodedSymbolTable;
+use alloc::collections::{BTreeSet, BinaryHeap};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+/// A Decoder peels symbols out of a collapsed RIBLT as the coded symbols arrive.
+///
+/// Collapsing and peeling an UnmanagedRatelessIBLT is destructive, so if we want to try again
+/// after more coded symbols arrive we have to collapse everything from scratch.
+/// The Decoder keeps its state between calls to add_coded_symbol instead.
+///
+/// - Symbols are peeled as soon as possible and kept, see recovered_so_far.
+/// - Symbols we have already peeled are removed from every coded symbol that arrives later.
+///
+/// This means that if the remote disconnects mid-stream we can still apply whatever we have
+/// recovered, and if it reconnects we can carry on from where we were.
+///
+/// The Decoder expects 'collapsed' coded symbols, one for each index in order.
+/// RatelessIBLT::collapse_coded_symbol produces these from the coded symbols a remote streams us.
+pub struct Decoder<T>
+where
+    T: symbol::Symbol,
+{
+    collapsed: CodedSymbolTable<T>,
+    recovered: Vec<symbol::Difference<T>>,
+    // the encoded recovered symbols, to notice one being peeled again
+    peeled: BTreeSet<Vec<u8>>,
+    // one mapping per recovered symbol, each positioned at the next index it lands on
+    mappings: Vec<mapping::RandomMapping>,
+    // (next index, position in recovered), so we can quickly find the symbols that land on a new index
+    next_indexes: BinaryHeap<Reverse<(usize, usize)>>,
+    // the number of coded symbols in collapsed that aren't empty, so is_empty doesn't scan them all
+    non_empty: usize,
+}
+
+impl<T> Default for Decoder<T>
+where
+    T: symbol::Symbol,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Decoder<T>
+where
+    T: symbol::Symbol,
+{
+    pub fn new() -> Self {
+        Decoder {
+            collapsed: CodedSymbolTable::new(),
+            recovered: Vec::new(),
+            peeled: BTreeSet::new(),
+            mappings: Vec::new(),
+            next_indexes: BinaryHeap::new(),
+            non_empty: 0,
+        }
+    }
+
+    /// Add the next collapsed coded symbol and peel everything we can.
+    ///
+    /// The coded symbol is expected to be at index len(), symbols we have already peeled are
+    /// removed from it before we attempt to peel it.
+    pub fn add_coded_symbol(&mut self, coded_symbol: &symbol::CodedSymbol<T>) {
+        let index = self.collapsed.len();
+        let mut coded_symbol = coded_symbol.clone();
+
+        while let Some(&Reverse((next_index, position))) = self.next_indexes.peek() {
+            if next_index != index {
+                break;
+            }
+            self.next_indexes.pop();
+
+            let (symbol, direction) = encoder::removal(&self.recovered[position]);
+            coded_symbol.apply(symbol, direction);
+            self.push_next_index(position);
+        }
+
+        self.collapsed.push(&coded_symbol);
+        if !self.collapsed.is_empty_at(index) {
+            self.non_empty += 1;
+        }
+        self.peel_from(index);
+    }
+
+    /// All the symbols we have peeled so far, in the order they were peeled.
+    pub fn recovered_so_far(&self) -> &[symbol::Difference<T>] {
+        &self.recovered
+    }
+
+    /// The number of coded symbols that have been added
+    pub fn len(&self) -> usize {
+        self.collapsed.len()
+    }
+
+    /// returns true if every coded symbol that has been added is now empty
+    /// If there are no CodedSymbols, this will return true
+    pub fn is_empty(&self) -> bool {
+        self.non_empty == 0
+    }
+
+    /// Classify the coded symbols we hold, see the DecodeStatus enum
+    pub fn decode_status(&self) -> encoder::DecodeStatus {
+        encoder::decode_status(&self.collapsed)
+    }
+
+    // Peel the CodedSymbol at the provided index, and any CodedSymbols that become peelable as a result.
+    fn peel_from(&mut self, index: usize) {
+        let mut candidates = vec![index];
+
+        while let Some(index) = candidates.pop() {
+            if self.collapsed.peeled_again_at().is_some() {
+                return;
+            }
+            let Some(difference) = encoder::peel_peek_at(&self.collapsed, index) else {
+                continue;
+            };
+
+            let (symbol, direction) = encoder::removal(&difference);
+            let encoded_symbol = symbol.encode_to_bytes();
+            // We can't genuinely peel a symbol twice, or more symbols than we have coded symbols,
+            // see encoder::peel_iter. Only forged coded symbols get us here.
+            if self.recovered.len() >= self.collapsed.len()
+                || !self.peeled.insert(encoded_symbol.clone())
+            {
+                self.collapsed.mark_peeled_again(index);
+                return;
+            }
+            let symbol_hash = symbol.hash_();
+            let mut item_mapping = mapping::RandomMapping::new(symbol);
+            // The mapping never ends, so we always find the first index past the end
+            let next_index = loop {
+                let i = item_mapping.next().unwrap();
+                if i >= self.collapsed.len() {
+                    break i;
+                }
+                let was_empty = self.collapsed.is_empty_at(i);
+                self.collapsed
+                    .apply_encoded(i, &encoded_symbol, symbol_hash, direction.clone());
+                match (was_empty, self.collapsed.is_empty_at(i)) {
+                    (false, true) => self.non_empty -= 1,
+                    (true, false) => self.non_empty += 1,
+                    _ => {}
+                }
+                candidates.push(i);
+            };
+
+            self.recovered.push(difference);
+            self.mappings.push(item_mapping);
+            self.next_indexes
+                .push(Reverse((next_index, self.recovered.len() - 1)));
+        }
+    }
+
+    fn push_next_index(&mut self, position: usize) {
+        if let Some(next_index) = self.mappings[position].next() {
+            self.next_indexes.push(Reverse((next_index, position)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{DecodeStatus, RatelessIBLT};
+    use crate::test_helpers::SimpleSymbol;
+    use std::collections::HashSet;
+
+    fn split(recovered: &[symbol::Difference<SimpleSymbol>]) -> (HashSet<u64>, HashSet<u64>) {
+        let (local, remote) = symbol::split_differences(recovered.to_vec());
+        (
+            local.iter().map(|s| s.value).collect(),
+            remote.iter().map(|s| s.value).collect(),
+        )
+    }
+
+    #[test]
+    fn test_streaming_decode() {
+        let items_local: HashSet<SimpleSymbol> =
+            (0..1000).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (30..1040).map(|value| SimpleSymbol { value }).collect();
+
+        let mut iblt_local = RatelessIBLT::new(items_local);
+        let mut iblt_remote = RatelessIBLT::new(items_remote);
+        let mut decoder = Decoder::new();
+
+        for index in 0.. {
+            let coded_symbol = iblt_remote.get_coded_symbol(index).unwrap();
+            decoder.add_coded_symbol(
+                &iblt_local
+                    .collapse_coded_symbol(index, &coded_symbol)
+                    .unwrap(),
+            );
+
+            // Whatever we have recovered so far must be part of the difference
+            let (local, remote) = split(decoder.recovered_so_far());
+            assert!(local.iter().all(|&x| x < 30));
+            assert!(remote.iter().all(|&x| x >= 1000));
+            if decoder.is_empty() {
+                break;
+            }
+        }
+
+        let (local, remote) = split(decoder.recovered_so_far());
+        assert_eq!(local, (0..30).collect());
+        assert_eq!(remote, (1000..1040).collect());
+        assert_eq!(decoder.decode_status(), DecodeStatus::Complete);
+    }
+
+    #[test]
+    fn test_is_empty_matches_scan() {
+        // is_empty keeps a count rather than scanning, check it against a scan after every
+        // coded symbol, for genuine and for forged ones
+        let items_local: HashSet<SimpleSymbol> =
+            (0..100).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (20..120).map(|value| SimpleSymbol { value }).collect();
+        let mut iblt_local = RatelessIBLT::new(items_local);
+        let mut iblt_remote = RatelessIBLT::new(items_remote);
+        let mut decoder = Decoder::new();
+        for index in 0..200 {
+            let coded_symbol = iblt_remote.get_coded_symbol(index).unwrap();
+            decoder.add_coded_symbol(
+                &iblt_local
+                    .collapse_coded_symbol(index, &coded_symbol)
+                    .unwrap(),
+            );
+            assert_eq!(decoder.is_empty(), encoder::is_empty(&decoder.collapsed));
+        }
+        assert!(decoder.is_empty());
+
+        let mut forged = Decoder::new();
+        for index in 0..200u64 {
+            let mut coded_symbol = symbol::CodedSymbol::new();
+            coded_symbol.apply(&SimpleSymbol { value: index % 3 }, symbol::Direction::Add);
+            coded_symbol.count = (index % 5) as i64 - 2;
+            forged.add_coded_symbol(&coded_symbol);
+            assert_eq!(forged.is_empty(), encoder::is_empty(&forged.collapsed));
+        }
+    }
+
+    #[test]
+    fn test_matches_collapse() {
+        let items_local: HashSet<SimpleSymbol> =
+            (0..100).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (10..110).map(|value| SimpleSymbol { value }).collect();
+
+        let mut iblt_local = RatelessIBLT::new(items_local);
+        let mut iblt_remote = RatelessIBLT::new(items_remote);
+        iblt_remote.extend_coded_symbols(0).unwrap();
+
+        // Stopping part way through the stream should recover the same symbols as collapsing
+        // and peeling the same prefix from scratch
+        for prefix_len in [1, 5, 10, 20, 30] {
+            let mut decoder = Decoder::new();
+            let mut prefix = crate::UnmanagedRatelessIBLT::new();
+            for index in 0..prefix_len {
+                let coded_symbol = iblt_remote.coded_symbols.get(index);
+                decoder.add_coded_symbol(
+                    &iblt_local
+                        .collapse_coded_symbol(index, &coded_symbol)
+                        .unwrap(),
+                );
+                prefix.add_coded_symbol(&coded_symbol);
+            }
+
+            let mut collapsed = iblt_local.collapse(&prefix).unwrap();
+            assert_eq!(
+                split(decoder.recovered_so_far()),
+                split(&collapsed.peel_all_symbols())
+            );
+            assert_eq!(decoder.is_empty(), collapsed.is_empty());
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// Encoders and decoders for ByteSymbols whose width is only known at runtime.
+//
+// Symbol::BYTE_ARRAY_LENGTH has to be known at compile time, so there is one enum variant per
+// supported width. This is shared by the language bindings (capi, wasm), which only deal in
+// byte strings and coded symbols in the wire format.
+
+use crate::decoder::Decoder;
+use crate::encoder::RatelessIBLT;
+use crate::error::Error;
+use crate::source::SymbolSource;
+use crate::symbol::{ByteSymbol, Difference, Fingerprint};
+use crate::wire;
+
+/// The symbol widths, in bytes, that can be chosen at runtime
+pub(crate) const SUPPORTED_WIDTHS: [usize; 6] = [4, 8, 16, 20, 32, 64];
+
+/// The number of bytes a coded symbol takes up on the wire for symbols of the width
+pub(crate) fn coded_symbol_len(width: usize) -> usize {
+    // the sum, plus the hash and count that every coded symbol has
+    width + wire::encoded_len::<ByteSymbol<0>>()
+}
+
+/// Produces a SymbolSource for whichever width the encoder turns out to need
+pub(crate) trait SourceFamily {
+    type Source<const N: usize>: SymbolSource<Symbol = ByteSymbol<N>>;
+
+    fn source<const N: usize>(self) -> Self::Source<N>;
+}
+
+/// Symbols that are already in memory, concatenated. The length must be a multiple of the width.
+pub(crate) struct ConcatenatedSymbols(pub Vec<u8>);
+
+impl SourceFamily for ConcatenatedSymbols {
+    type Source<const N: usize> = Vec<ByteSymbol<N>>;
+
+    fn source<const N: usize>(self) -> Vec<ByteSymbol<N>> {
+        self.0
+            .chunks_exact(N)
+            .map(|chunk| ByteSymbol(chunk.try_into().unwrap()))
+            .collect()
+    }
+}
+
+/// A difference peeled by an AnyDecoder
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ByteDifference {
+    /// true if the symbol is only in the local set, false if only in the remote set
+    pub local: bool,
+    pub symbol: Vec<u8>,
+}
+
+pub(crate) enum AnyEncoder<F: SourceFamily> {
+    W4(RatelessIBLT<ByteSymbol<4>, F::Source<4>>),
+    W8(RatelessIBLT<ByteSymbol<8>, F::Source<8>>),
+    W16(RatelessIBLT<ByteSymbol<16>, F::Source<16>>),
+    W20(RatelessIBLT<ByteSymbol<20>, F::Source<20>>),
+    W32(RatelessIBLT<ByteSymbol<32>, F::Source<32>>),
+    W64(RatelessIBLT<ByteSymbol<64>, F::Source<64>>),
+}
+
+pub(crate) struct DecoderState<const N: usize> {
+    decoder: Decoder<ByteSymbol<N>>,
+    // how many of the recovered symbols have been handed out by next_difference
+    returned: usize,
+}
+
+impl<const N: usize> DecoderState<N> {
+    fn new() -> Self {
+        DecoderState {
+            decoder: Decoder::new(),
+            returned: 0,
+        }
+    }
+
+    fn width(&self) -> usize {
+        N
+    }
+}
+
+pub(crate) enum AnyDecoder {
+    W4(DecoderState<4>),
+    W8(DecoderState<8>),
+    W16(DecoderState<16>),
+    W20(DecoderState<20>),
+    W32(DecoderState<32>),
+    W64(DecoderState<64>),
+}
+
+macro_rules! for_each_width {
+    ($value:expr, $any:ident, $inner:ident => $body:expr) => {
+        match $value {
+            $any::W4($inner) => $body,
+            $any::W8($inner) => $body,
+            $any::W16($inner) => $body,
+            $any::W20($inner) => $body,
+            $any::W32($inner) => $body,
+            $any::W64($inner) => $body,
+        }
+    };
+}
+
+impl<F: SourceFamily> AnyEncoder<F> {
+    /// None if the width is not supported
+    pub(crate) fn new(width: usize, family: F) -> Option<Self> {
+        Some(match width {
+            4 => AnyEncoder::W4(RatelessIBLT::new(family.source())),
+            8 => AnyEncoder::W8(RatelessIBLT::new(family.source())),
+            16 => AnyEncoder::W16(RatelessIBLT::new(family.source())),
+            20 => AnyEncoder::W20(RatelessIBLT::new(family.source())),
+            32 => AnyEncoder::W32(RatelessIBLT::new(family.source())),
+            64 => AnyEncoder::W64(RatelessIBLT::new(family.source())),
+            _ => return None,
+        })
+    }
+
+    /// The coded symbol at the index, in the wire format
+    pub(crate) fn get_coded_symbol(&mut self, index: usize) -> Result<Vec<u8>, Error> {
+        for_each_width!(self, AnyEncoder, encoder => Ok(wire::encode_coded_symbol(&encoder.get_coded_symbol(index)?)))
+    }
+
+    /// Our coded symbol at the index minus the remote's, both in the wire format
+    pub(crate) fn collapse_coded_symbol(
+        &mut self,
+        index: usize,
+        remote: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        for_each_width!(self, AnyEncoder, encoder => {
+            let remote = wire::decode_coded_symbol(remote)?;
+            Ok(wire::encode_coded_symbol(&encoder.collapse_coded_symbol(index, &remote)?))
+        })
+    }
+
+    pub(crate) fn fingerprint(&self) -> Result<Fingerprint, Error> {
+        for_each_width!(self, AnyEncoder, encoder => encoder.fingerprint())
+    }
+}
+
+impl AnyDecoder {
+    /// None if the width is not supported
+    pub(crate) fn new(width: usize) -> Option<Self> {
+        Some(match width {
+            4 => AnyDecoder::W4(DecoderState::new()),
+            8 => AnyDecoder::W8(DecoderState::new()),
+            16 => AnyDecoder::W16(DecoderState::new()),
+            20 => AnyDecoder::W20(DecoderState::new()),
+            32 => AnyDecoder::W32(DecoderState::new()),
+            64 => AnyDecoder::W64(DecoderState::new()),
+            _ => return None,
+        })
+    }
+
+    /// Add the next collapsed coded symbol, in the wire format
+    pub(crate) fn add_coded_symbol(&mut self, coded_symbol: &[u8]) -> Result<(), Error> {
+        for_each_width!(self, AnyDecoder, state => {
+            state.decoder.add_coded_symbol(&wire::decode_coded_symbol(coded_symbol)?);
+            Ok(())
+        })
+    }
+
+    /// The next difference that hasn't been returned yet, each difference is only returned once
+    pub(crate) fn next_difference(&mut self) -> Option<ByteDifference> {
+        for_each_width!(self, AnyDecoder, state => {
+            let difference = state.decoder.recovered_so_far().get(state.returned)?;
+            state.returned += 1;
+            Some(ByteDifference {
+                local: matches!(difference, Difference::Local(_)),
+                symbol: difference.symbol().0.to_vec(),
+            })
+        })
+    }
+
+    /// The width of the symbols, in bytes
+    pub(crate) fn width(&self) -> usize {
+        for_each_width!(self, AnyDecoder, state => state.width())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        for_each_width!(self, AnyDecoder, state => state.decoder.is_empty())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        for_each_width!(self, AnyDecoder, state => state.decoder.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn concatenated(ids: impl Iterator<Item = u32>) -> ConcatenatedSymbols {
+        ConcatenatedSymbols(ids.flat_map(|id| id.to_le_bytes()).collect())
+    }
+
+    #[test]
+    fn test_runtime_width() {
+        assert!(AnyEncoder::new(7, concatenated(0..10)).is_none());
+        assert!(AnyDecoder::new(7).is_none());
+        for width in SUPPORTED_WIDTHS {
+            assert_eq!(AnyDecoder::new(width).unwrap().width(), width);
+        }
+
+        let mut local = AnyEncoder::new(4, concatenated(0..100)).unwrap();
+        let mut remote = AnyEncoder::new(4, concatenated(2..101)).unwrap();
+        let mut decoder = AnyDecoder::new(4).unwrap();
+        assert_eq!(remote.fingerprint().unwrap().count, 99);
+
+        loop {
+            let coded_symbol = remote.get_coded_symbol(decoder.len()).unwrap();
+            assert_eq!(coded_symbol.len(), coded_symbol_len(4));
+            let collapsed = local
+                .collapse_coded_symbol(decoder.len(), &coded_symbol)
+                .unwrap();
+            decoder.add_coded_symbol(&collapsed).unwrap();
+            if decoder.is_empty() {
+                break;
+            }
+        }
+
+        let mut differences: Vec<_> = std::iter::from_fn(|| decoder.next_difference())
+            .map(|d| (d.local, u32::from_le_bytes(d.symbol.try_into().unwrap())))
+            .collect();
+        differences.sort_by_key(|&(_, id)| id);
+        assert_eq!(differences, vec![(true, 0), (true, 1), (false, 100)]);
+        assert_eq!(decoder.next_difference(), None);
+
+        assert!(matches!(
+            decoder.add_coded_symbol(&[0u8; 3]),
+            Err(Error::InvalidFormat(_))
+        ));
+    }
+}
@@ -1,7 +1,17 @@
+use crate::error::Error;
 use crate::mapping;
+use crate::source::{SymbolSource, Windowed};
 use crate::symbol;
+use crate::table::CodedSymbolTable;
+#[cfg(feature = "std")]
+use crate::wire;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
 
-/// Constant for block size. 
+/// Constant for block size.
 /// As it can be computationally expensive to iterate over the set, it makes sense to generate
 /// a 'block' of coded symbols at a time.
 ///
@@ -11,66 +21,83 @@ use crate::symbol;
 /// It might make sense to set a BLOCK_SIZE that is inversly proportional to size of the Symbol
 pub const BLOCK_SIZE: usize = 1024;
 
+// Identifies a file written by RatelessIBLT::save_prefix
+#[cfg(feature = "std")]
+const PREFIX_MAGIC: &[u8; 8] = b"RIBLTPFX";
+#[cfg(feature = "std")]
+const PREFIX_VERSION: u32 = 1;
+
 /// There is a managed and unmanaged version of the RatelessIBLT
 /// It is expected that the managed version will be used when we have access to the set
 /// The managed version will generate coded symbols as needed (for efficiencey, it will generate a 'block' of coded symbols at a time)
 /// The unmanaged version will be used whereever we don't have access to the set
-pub struct RatelessIBLT<T, I>
+///
+/// The set is anything that implements SymbolSource, see the SymbolSource trait.
+pub struct RatelessIBLT<T, S>
 where
     T: symbol::Symbol,
-    I: IntoIterator<Item = T> + Clone,
+    S: SymbolSource<Symbol = T>,
 {
-    pub coded_symbols: Vec<symbol::CodedSymbol<T>>,
-    set_iterator: I,
+    pub coded_symbols: CodedSymbolTable<T>,
+    pub(crate) source: S,
+    // taken from the CodedSymbol at index 0 when it is generated, before anything can peel it
+    fingerprint: Option<symbol::Fingerprint>,
 }
 
-// It might be nice to 'peel' the symbols out as an iterator
-// impl<T, I> Iterator for RatelessIBLT<T, I>
-// where
-//     T: symbol::Symbol,
-//     I: IntoIterator<Item = T> + Clone,
-// {
-//     type Item = T;
-// 
-//     fn next(&mut self) -> Option<Self::Item> {
-//         todo!();
-//     }
-// }
-
-impl<T, I> RatelessIBLT<T, I>
+impl<T, S> RatelessIBLT<T, S>
 where
     T: symbol::Symbol,
-    I: IntoIterator<Item = T> + Clone,
+    S: SymbolSource<Symbol = T>,
 {
     /// CodedSymbols are created as required, this method extends the codedSymbols to at least the provided index
-    pub fn extend_coded_symbols(&mut self, index: usize) {
+    ///
+    /// If scanning the set fails, the error is returned and the coded symbols are left as they were.
+    pub fn extend_coded_symbols(&mut self, index: usize) -> Result<(), Error> {
         // extend the coded symbols so that we can access the coded symbol at the provided index
         // if the index is within the current length of the coded_symbols, we do nothing
         let current_len = self.coded_symbols.len();
         if index < current_len {
-            return;
+            return Ok(());
         }
 
         // we should generate at minimum the BLOCK_SIZE number of coded symbols
         // Aternatively, we could always generate up to the index + the block size
         let extend_until = usize::max(index + 1, current_len + BLOCK_SIZE);
 
-        for _ in current_len..extend_until {
-            self.coded_symbols.push(symbol::CodedSymbol::new());
-        }
+        self.coded_symbols.resize(extend_until);
 
-        let cloned_set_iterator = self.set_iterator.clone();
+        let result = self.apply_block(current_len..extend_until);
+        if result.is_err() {
+            // don't leave a partially generated block behind
+            self.coded_symbols.truncate(current_len);
+        } else if current_len == 0 {
+            self.fingerprint = Some((&self.coded_symbols.get(0)).into());
+        }
+        result
+    }
 
-        for item in cloned_set_iterator.into_iter() {
+    // Scan the set, adding every item to the coded symbols in the range
+    fn apply_block(&mut self, range: Range<usize>) -> Result<(), Error> {
+        for item in self.source.scan().map_err(Error::from_source)? {
+            let item = item.map_err(Error::from_source)?;
             let item_mapping = mapping::RandomMapping::new(&item);
+            // encode the item once, rather than once for every coded symbol it is mapped to
+            let encoded_item = item.encode_to_bytes();
+            let item_hash = item.hash_();
 
             for i in item_mapping
-                .take_while(|&x| x < extend_until)
-                .filter(|&x| x >= current_len)
+                .take_while(|&x| x < range.end)
+                .filter(|&x| x >= range.start)
             {
-                self.coded_symbols[i].apply(&item, symbol::Direction::Add);
+                self.coded_symbols.apply_encoded(
+                    i,
+                    &encoded_item,
+                    item_hash,
+                    symbol::Direction::Add,
+                );
             }
         }
+        Ok(())
     }
 
     /// Returns the coded symbol at the provided index.
@@ -78,45 +105,229 @@ where
     /// It is expected that this will be called in a loop to stream the coded symbols to a remote server.
     ///
     /// If the index is greater than the current length of the coded symbols, we extend the coded symbols.
-    pub fn get_coded_symbol(&mut self, index: usize) -> symbol::CodedSymbol<T> {
-        if index >= self.coded_symbols.len() {
-            self.extend_coded_symbols(index);
-        }
-        self.coded_symbols[index].clone()
+    pub fn get_coded_symbol(&mut self, index: usize) -> Result<symbol::CodedSymbol<T>, Error> {
+        self.extend_coded_symbols(index)?;
+        Ok(self.coded_symbols.get(index))
+    }
+
+    /// Subtract a single remote coded symbol from our coded symbol at the same index.
+    ///
+    /// The result is what the Decoder expects to be given, one index at a time.
+    pub fn collapse_coded_symbol(
+        &mut self,
+        index: usize,
+        other: &symbol::CodedSymbol<T>,
+    ) -> Result<symbol::CodedSymbol<T>, Error> {
+        self.extend_coded_symbols(index)?;
+        Ok(self.coded_symbols.get(index).collapse(other))
     }
 
     /// Constructing a new RatelessIBLT requires a set of symbols that can be iterated over.
     /// The RatelessIBLT will generate coded symbols as needed. So this set may be iterated over multiple times.
     ///
     /// It is the responsibility of the calling code to create a new RatelessIBLT if the set changes.
-    pub fn new(set_iterator: I) -> Self {
-        let mut riblt = RatelessIBLT {
-            coded_symbols: Vec::new(),
-            set_iterator,
+    pub fn new(source: S) -> Self {
+        Self::with_table(source, CodedSymbolTable::new())
+    }
+
+    /// Same as new, but the coded symbols are generated into the provided table.
+    ///
+    /// This allows the coded symbols to be memory mapped, see CodedSymbolTable::with_mmap_dir.
+    /// The table is expected to be empty, any CodedSymbols in it are assumed to be the start of
+    /// this set's coded symbols.
+    pub fn with_table(source: S, coded_symbols: CodedSymbolTable<T>) -> Self {
+        let fingerprint = (!coded_symbols.is_empty()).then(|| (&coded_symbols.get(0)).into());
+        RatelessIBLT {
+            coded_symbols,
+            source,
+            fingerprint,
+        }
+    }
+
+    /// Write the coded symbols generated so far, so they don't need to be regenerated after a restart.
+    ///
+    /// Alongside the coded symbols we write the symbol length, the Symbol::HASH_SCHEME and a
+    /// fingerprint of the set (the hash and count of every symbol in the set).
+    /// See with_cached_prefix for loading them again.
+    #[cfg(feature = "std")]
+    pub fn save_prefix<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let fingerprint = self.fingerprint()?;
+
+        writer.write_all(PREFIX_MAGIC)?;
+        writer.write_all(&PREFIX_VERSION.to_le_bytes())?;
+        writer.write_all(&(T::BYTE_ARRAY_LENGTH as u64).to_le_bytes())?;
+        writer.write_all(&T::HASH_SCHEME.to_le_bytes())?;
+        writer.write_all(&fingerprint.hash.to_le_bytes())?;
+        writer.write_all(&fingerprint.count.to_le_bytes())?;
+        writer.write_all(&(self.coded_symbols.len() as u64).to_le_bytes())?;
+        for coded_symbol in self.coded_symbols.iter() {
+            wire::write_coded_symbol(writer, &coded_symbol)?;
+        }
+        Ok(())
+    }
+
+    /// Construct a RatelessIBLT with the coded symbols previously written by save_prefix.
+    ///
+    /// The set is iterated over once to check its fingerprint against the saved one, which is
+    /// much cheaper than regenerating a long prefix one block at a time.
+    ///
+    /// Returns an error (rather than coded symbols that don't match the set) if the set, the
+    /// symbol length or the Symbol::HASH_SCHEME have changed since the prefix was saved.
+    #[cfg(feature = "std")]
+    pub fn with_cached_prefix<R: Read>(source: S, reader: &mut R) -> Result<Self, Error> {
+        let magic: [u8; 8] = wire::read_array(reader)?;
+        if &magic != PREFIX_MAGIC {
+            return Err(Error::InvalidFormat("not a saved RatelessIBLT prefix"));
+        }
+        if u32::from_le_bytes(wire::read_array(reader)?) != PREFIX_VERSION {
+            return Err(Error::InvalidFormat("unsupported prefix version"));
+        }
+        let symbol_length = u64::from_le_bytes(wire::read_array(reader)?) as usize;
+        if symbol_length != T::BYTE_ARRAY_LENGTH {
+            return Err(Error::SymbolLengthMismatch {
+                expected: T::BYTE_ARRAY_LENGTH,
+                found: symbol_length,
+            });
+        }
+        let hash_scheme = u32::from_le_bytes(wire::read_array(reader)?);
+        if hash_scheme != T::HASH_SCHEME {
+            return Err(Error::HashSchemeMismatch {
+                expected: T::HASH_SCHEME,
+                found: hash_scheme,
+            });
+        }
+        let fingerprint = symbol::Fingerprint {
+            hash: u64::from_le_bytes(wire::read_array(reader)?),
+            count: i64::from_le_bytes(wire::read_array(reader)?),
         };
-        riblt
+        let len = u64::from_le_bytes(wire::read_array(reader)?) as usize;
+
+        let mut riblt = RatelessIBLT::new(source);
+        if riblt.fingerprint()? != fingerprint {
+            return Err(Error::FingerprintMismatch);
+        }
+        riblt.fingerprint = Some(fingerprint);
+
+        for _ in 0..len {
+            riblt.coded_symbols.push(&wire::read_coded_symbol(reader)?);
+        }
+        Ok(riblt)
+    }
+
+    /// The fingerprint of the set, see the Fingerprint struct.
+    ///
+    /// This is free once the first block of coded symbols has been generated, otherwise we scan
+    /// the set. Compare it with the remote's before streaming any coded symbols (the Header
+    /// carries it), if they match there is nothing to reconcile.
+    pub fn fingerprint(&self) -> Result<symbol::Fingerprint, Error> {
+        if let Some(fingerprint) = self.fingerprint {
+            return Ok(fingerprint);
+        }
+        let mut fingerprint = symbol::Fingerprint::default();
+        for item in self.source.scan().map_err(Error::from_source)? {
+            fingerprint.add(&item.map_err(Error::from_source)?);
+        }
+        Ok(fingerprint)
+    }
+
+    // Extend the coded symbols so that we hold at least len of them
+    fn extend_to_len(&mut self, len: usize) -> Result<(), Error> {
+        if len > 0 {
+            self.extend_coded_symbols(len - 1)?;
+        }
+        Ok(())
     }
 
     /// Join two vectors of codedSymbols together produced from two distinct sets.
     /// The results are only valid if there were no duplicates between the original sets.
-    pub fn combine(&mut self, other: &RatelessIBLT<T, I>) -> UnmanagedRatelessIBLT<T> {
-        // if the passed in RatelessIBLT has more coded symbols than self, we extend Self
-        self.extend_coded_symbols(other.coded_symbols.len());
-        combine(&self.coded_symbols, &other.coded_symbols)
+    ///
+    /// Both are extended to hold as many coded symbols as the longer of the two, and the result
+    /// holds exactly that many.
+    pub fn combine<S2: SymbolSource<Symbol = T>>(
+        &mut self,
+        other: &mut RatelessIBLT<T, S2>,
+    ) -> Result<UnmanagedRatelessIBLT<T>, Error> {
+        let len = usize::max(self.coded_symbols.len(), other.coded_symbols.len());
+        self.extend_to_len(len)?;
+        other.extend_to_len(len)?;
+        let mut combined_block = self.coded_symbols.prefix(len);
+        combined_block.combine_from(&other.coded_symbols, 0..len);
+        Ok(UnmanagedRatelessIBLT::with_table(combined_block))
     }
 
     /// Subtract a remote sequence of codedSymbols from a local sequence.
-    pub fn collapse(&mut self, other: &UnmanagedRatelessIBLT<T>) -> UnmanagedRatelessIBLT<T> {
-        // if the passed in RatelessIBLT has more coded symbols than self, we extend Self
-        self.extend_coded_symbols(other.coded_symbols.len());
-        collapse(&self.coded_symbols, &other.coded_symbols)
+    ///
+    /// We are extended to hold as many coded symbols as the remote, and the result holds
+    /// exactly as many coded symbols as the remote.
+    ///
+    /// The result is held in memory, see collapse_into for writing it to a memory mapped table.
+    pub fn collapse(
+        &mut self,
+        other: &UnmanagedRatelessIBLT<T>,
+    ) -> Result<UnmanagedRatelessIBLT<T>, Error> {
+        let mut collapsed = UnmanagedRatelessIBLT::new();
+        self.collapse_into(other, &mut collapsed)?;
+        Ok(collapsed)
     }
 
-    /// If possible, peel a single symbol from the RatelessIBLT
-    pub fn peel_one_symbol(&mut self) -> symbol::PeelableResult<T> {
-        peel_one_symbol(&mut self.coded_symbols)
+    /// Same as collapse, but the result is written into an existing UnmanagedRatelessIBLT
+    /// rather than allocating a new one.
+    ///
+    /// The result is written in place, so if collapsed was created with a memory mapped table
+    /// (see UnmanagedRatelessIBLT::with_table) it stays memory mapped, and the collapsed prefix
+    /// doesn't have to fit in RAM.
+    pub fn collapse_into(
+        &mut self,
+        other: &UnmanagedRatelessIBLT<T>,
+        collapsed: &mut UnmanagedRatelessIBLT<T>,
+    ) -> Result<(), Error> {
+        let len = other.coded_symbols.len();
+        collapsed.coded_symbols.truncate(len);
+        self.collapse_range(other, collapsed, 0..len)
     }
 
+    /// Write our coded symbols minus the other's into collapsed, only for the indexes in the range.
+    ///
+    /// This is used when the remote streams coded symbols to us in batches, each batch only needs
+    /// the newly arrived indexes collapsing. collapsed grows as needed, and the coded symbols it
+    /// holds outside the range are left alone.
+    ///
+    /// Returns an error if the other UnmanagedRatelessIBLT doesn't hold range.end coded symbols,
+    /// or if scanning the set fails.
+    pub fn collapse_range(
+        &mut self,
+        other: &UnmanagedRatelessIBLT<T>,
+        collapsed: &mut UnmanagedRatelessIBLT<T>,
+        range: Range<usize>,
+    ) -> Result<(), Error> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        if other.coded_symbols.len() < range.end {
+            return Err(Error::LengthMismatch {
+                left: range.end,
+                right: other.coded_symbols.len(),
+            });
+        }
+        self.extend_to_len(range.end)?;
+        if collapsed.coded_symbols.len() < range.end {
+            collapsed.coded_symbols.resize(range.end);
+        }
+        collapsed
+            .coded_symbols
+            .copy_from(&self.coded_symbols, range.clone());
+        collapsed
+            .coded_symbols
+            .collapse_from(&other.coded_symbols, range);
+        Ok(())
+    }
+
+    /// Peel the symbols out one at a time, stops once nothing more can be peeled
+    ///
+    /// Symbols are only peeled as the iterator is advanced.
+    pub fn peel_iter(&mut self) -> impl Iterator<Item = symbol::Difference<T>> + '_ {
+        peel_iter(&mut self.coded_symbols)
+    }
 
     /// Peel all symbols from the RatelessIBLT that we possibly can
     ///
@@ -125,29 +336,47 @@ where
     ///
     /// We expect to call this on an UnmanagedRatelessIBLT that was produced from collapsing a
     /// remote against our local
-    pub fn peel_all_symbols(&mut self) -> Vec<symbol::PeelableResult<T>> {
-        let mut peeled_symbols = Vec::new();
-        loop {
-            let peeled_symbol = self.peel_one_symbol();
-            match peeled_symbol {
-                symbol::PeelableResult::NotPeelable => {
-                    break;
-                }
-                _ => {
-                    peeled_symbols.push(peeled_symbol);
-                }
-            }
-        }
-        peeled_symbols
+    pub fn peel_all_symbols(&mut self) -> Vec<symbol::Difference<T>> {
+        self.peel_iter().collect()
     }
 
     /// returns true if there are no symbols
     /// If we can't peel any symbols, but it is not empty it means that we have symbols that
     /// can't be recovered
     /// We can't know if the RatelessIBLT is empty until we have iterated over the set
-    pub fn is_empty(&mut self) -> bool {
-        self.extend_coded_symbols(0); // This does nothing if we already have some coded symbols
-        is_empty(&self.coded_symbols)
+    pub fn is_empty(&mut self) -> Result<bool, Error> {
+        self.extend_coded_symbols(0)?; // This does nothing if we already have some coded symbols
+        Ok(is_empty(&self.coded_symbols))
+    }
+
+    /// Classify the coded symbols we hold, see the DecodeStatus enum
+    ///
+    /// Like is_empty, this is only meaningful once we have peeled all the symbols we can
+    pub fn decode_status(&mut self) -> Result<DecodeStatus, Error> {
+        self.extend_coded_symbols(0)?;
+        Ok(decode_status(&self.coded_symbols))
+    }
+}
+
+impl<T, S> RatelessIBLT<T, Windowed<S>>
+where
+    T: symbol::TimestampedSymbol,
+    S: SymbolSource<Symbol = T>,
+{
+    /// Construct a RatelessIBLT over only the symbols inserted at or before the cutoff.
+    ///
+    /// When a set is constantly being inserted into and gossiped between servers, the most
+    /// recent symbols are unlikely to have reached everyone yet. Leaving them out means the
+    /// repair mechanism doesn't waste effort on differences that gossip is about to resolve.
+    ///
+    /// Both sides must use the same cutoff, otherwise the coded symbols won't collapse to the
+    /// difference. Send it to the remote in the Header.
+    pub fn with_window(source: S, cutoff: u64) -> Self {
+        Self::new(Windowed::new(source, cutoff))
+    }
+
+    pub fn cutoff(&self) -> u64 {
+        self.source.cutoff()
     }
 }
 
@@ -166,65 +395,104 @@ pub struct UnmanagedRatelessIBLT<T>
 where
     T: symbol::Symbol,
 {
-    pub coded_symbols: Vec<symbol::CodedSymbol<T>>,
+    pub coded_symbols: CodedSymbolTable<T>,
+}
+
+impl<T> Default for UnmanagedRatelessIBLT<T>
+where
+    T: symbol::Symbol,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-// It might be nice to 'peel' the symbols out as an iterator
-// impl<T> Iterator for UnmanagedRatelessIBLT<T>
-// where
-//     T: symbol::Symbol,
-// {
-//     type Item = T;
-// 
-//     fn next(&mut self) -> Option<Self::Item> {
-//         //TODO
-//         None
-//     }
-// }
 impl<T> UnmanagedRatelessIBLT<T>
 where
     T: symbol::Symbol,
 {
     pub fn new() -> Self {
-        return UnmanagedRatelessIBLT {
-            coded_symbols: Vec::new(),
-        };
+        Self::with_table(CodedSymbolTable::new())
+    }
+
+    /// Same as new, but the coded symbols are added to the provided table.
+    ///
+    /// This allows the coded symbols to be memory mapped, see CodedSymbolTable::with_mmap_dir.
+    pub fn with_table(coded_symbols: CodedSymbolTable<T>) -> Self {
+        UnmanagedRatelessIBLT { coded_symbols }
     }
 
     /// Join two vectors of codedSymbols together produced from two distinct sets.
     /// The results are only valid if there were no duplicates between the original sets.
-    pub fn combine(&self, other: &UnmanagedRatelessIBLT<T>) -> UnmanagedRatelessIBLT<T> {
+    ///
+    /// Both must hold the same number of coded symbols, see truncate_to.
+    pub fn combine(
+        &self,
+        other: &UnmanagedRatelessIBLT<T>,
+    ) -> Result<UnmanagedRatelessIBLT<T>, Error> {
         combine(&self.coded_symbols, &other.coded_symbols)
     }
     /// Subtract a remote sequence of codedSymbols from a local sequence.
-    pub fn collapse(&self, other: &UnmanagedRatelessIBLT<T>) -> UnmanagedRatelessIBLT<T> {
+    ///
+    /// Both must hold the same number of coded symbols, see truncate_to.
+    ///
+    /// The result is held in memory, use collapse_into to keep a memory mapped table mapped.
+    pub fn collapse(
+        &self,
+        other: &UnmanagedRatelessIBLT<T>,
+    ) -> Result<UnmanagedRatelessIBLT<T>, Error> {
         collapse(&self.coded_symbols, &other.coded_symbols)
     }
-    /// If possible, peel a single symbol from the RatelessIBLT
-    pub fn peel_one_symbol(&mut self) -> symbol::PeelableResult<T> {
-        peel_one_symbol(&mut self.coded_symbols)
+    /// Same as collapse, but done in place rather than allocating a new UnmanagedRatelessIBLT.
+    ///
+    /// If our table is memory mapped, so is the result.
+    pub fn collapse_into(&mut self, other: &UnmanagedRatelessIBLT<T>) -> Result<(), Error> {
+        check_lengths(&self.coded_symbols, &other.coded_symbols)?;
+        self.collapse_range(other, 0..other.coded_symbols.len())
+    }
+    /// Subtract the other's coded symbols from ours in place, only for the indexes in the range.
+    ///
+    /// Returns an error unless both hold at least range.end coded symbols.
+    pub fn collapse_range(
+        &mut self,
+        other: &UnmanagedRatelessIBLT<T>,
+        range: Range<usize>,
+    ) -> Result<(), Error> {
+        let len = usize::min(self.coded_symbols.len(), other.coded_symbols.len());
+        if len < range.end {
+            return Err(Error::LengthMismatch {
+                left: self.coded_symbols.len(),
+                right: other.coded_symbols.len(),
+            });
+        }
+        self.coded_symbols
+            .collapse_from(&other.coded_symbols, range);
+        Ok(())
+    }
+    /// Drop every coded symbol past the provided length.
+    ///
+    /// Combining and collapsing require both sides to hold the same number of coded symbols, use
+    /// this to explicitly drop the extra coded symbols from the longer side.
+    pub fn truncate_to(&mut self, len: usize) {
+        self.coded_symbols.truncate(len);
+    }
+    /// Peel the symbols out one at a time, stops once nothing more can be peeled
+    ///
+    /// Symbols are only peeled as the iterator is advanced, so the differences can be streamed
+    /// somewhere without collecting them all first. Use next() to peel a single symbol.
+    /// Call the is_empty method afterwards to check if there are any symbols left
+    pub fn peel_iter(&mut self) -> impl Iterator<Item = symbol::Difference<T>> + '_ {
+        peel_iter(&mut self.coded_symbols)
     }
     /// Peel all symbols from the RatelessIBLT that we possibly can
     /// Call the is_empty method to check if there are any symbols left
-    pub fn peel_all_symbols(&mut self) -> Vec<symbol::PeelableResult<T>> {
-        let mut peeled_symbols = Vec::new();
-        loop {
-            let peeled_symbol = self.peel_one_symbol();
-            match peeled_symbol {
-                symbol::PeelableResult::NotPeelable => {
-                    break;
-                }
-                _ => {
-                    peeled_symbols.push(peeled_symbol);
-                }
-            }
-        }
-        peeled_symbols
+    pub fn peel_all_symbols(&mut self) -> Vec<symbol::Difference<T>> {
+        self.peel_iter().collect()
     }
     /// Add a coded symbol
     /// The expected use is that a remote server is streaming us codedSymbols and we are adding them to our local copy.
     pub fn add_coded_symbol(&mut self, other: &symbol::CodedSymbol<T>) {
-        self.coded_symbols.push(other.clone());
+        self.coded_symbols.push(other);
     }
 
     /// returns true if there are no symbols
@@ -235,95 +503,205 @@ where
         //It might be good to panic if there are no coded symbols
         is_empty(&self.coded_symbols)
     }
+
+    /// Classify the coded symbols we hold, see the DecodeStatus enum
+    ///
+    /// Call this after peel_all_symbols to tell apart 'ask the remote for more coded symbols'
+    /// from 'these coded symbols can never be decoded'
+    pub fn decode_status(&self) -> DecodeStatus {
+        decode_status(&self.coded_symbols)
+    }
 }
 
 // a function that takes a set that can be iterted over and an offset and returns a block of coded symbols
 
-pub fn peel_one_symbol<T: symbol::Symbol>(
-    block: &mut Vec<symbol::CodedSymbol<T>>,
-) -> symbol::PeelableResult<T> {
-    if block.is_empty() {
-        return symbol::PeelableResult::NotPeelable;
-    }
-    let mut peelable_result = symbol::PeelableResult::NotPeelable;
+// Same as CodedSymbolTable::peel_peek, but also requires that the peeled symbol is actually mapped
+// to the index it was found at.
+// A symbol that is not mapped to its own index can't be removed from that CodedSymbol, so
+// peeling it would leave the CodedSymbol unchanged and we would peel it forever.
+pub(crate) fn peel_peek_at<T: symbol::Symbol>(
+    block: &CodedSymbolTable<T>,
+    index: usize,
+) -> Option<symbol::Difference<T>> {
+    block
+        .peel_peek(index)
+        .filter(|difference| mapping::RandomMapping::maps_to(difference.symbol(), index))
+}
 
-    // we check if each codedSymbol can be peeled,
-    // if it can, we exit the loop, remove it from the block and return the result
-    for symbol in block.iter() {
-        peelable_result = symbol.peel_peek();
+/// Lazily peel symbols out of a block, each call to next peels at most one symbol
+// Peeling a symbol only changes the coded symbols it maps to, so those are the only ones checked
+// again, rather than rescanning the whole block for every peel.
+//
+// A genuine peel removes the symbol from every coded symbol, so no symbol is peeled twice and
+// there can't be more peels than coded symbols. Forged coded symbols can make a symbol look pure
+// again after it was peeled. Peeling stops there and decode_status reports the block as
+// inconsistent.
+pub fn peel_iter<T: symbol::Symbol>(
+    block: &mut CodedSymbolTable<T>,
+) -> impl Iterator<Item = symbol::Difference<T>> + '_ {
+    let len = block.len();
+    // popped from the end, so the first pass goes through the block in order
+    let mut candidates: Vec<usize> = (0..len).rev().collect();
+    let mut peeled: BTreeSet<Vec<u8>> = BTreeSet::new();
 
-        match peelable_result {
-            symbol::PeelableResult::NotPeelable => continue,
-            _ => {
-                remove_symbol_from_block(block, peelable_result.clone());
-                break;
-            }
+    core::iter::from_fn(move || {
+        if block.peeled_again_at().is_some() {
+            return None;
         }
-    }
-
-    peelable_result
-}
+        while let Some(index) = candidates.pop() {
+            let Some(difference) = peel_peek_at(block, index) else {
+                continue;
+            };
+            let (symbol, direction) = removal(&difference);
+            let encoded_symbol = symbol.encode_to_bytes();
+            if peeled.len() == len || !peeled.insert(encoded_symbol.clone()) {
+                block.mark_peeled_again(index);
+                return None;
+            }
 
-pub fn remove_symbol_from_block<T: symbol::Symbol>(
-    block: &mut Vec<symbol::CodedSymbol<T>>,
-    symbol_result: symbol::PeelableResult<T>,
-) {
-    let direction;
-    let symbol: T = match symbol_result {
-        symbol::PeelableResult::Local(symbol) => {
-            direction = symbol::Direction::Remove;
-            symbol
-        }
-        symbol::PeelableResult::Remote(symbol) => {
-            direction = symbol::Direction::Add;
-            symbol
-        }
-        symbol::PeelableResult::NotPeelable => {
-            panic!("Can't remove nothing from a block");
+            let symbol_hash = symbol.hash_();
+            for i in mapping::RandomMapping::new(symbol).take_while(|&i| i < len) {
+                block.apply_encoded(i, &encoded_symbol, symbol_hash, direction.clone());
+                candidates.push(i);
+            }
+            return Some(difference);
         }
-    };
-
-    let item_mapping = mapping::RandomMapping::new(&symbol);
-
-    let block_len = block.len();
+        None
+    })
+}
 
-    for i in item_mapping.take_while(|&x| (x as usize) < block_len) {
-        block[i as usize].apply(&symbol, direction.clone());
+// The symbol and direction required to remove a peeled symbol from a CodedSymbol
+pub(crate) fn removal<T: symbol::Symbol>(
+    difference: &symbol::Difference<T>,
+) -> (&T, symbol::Direction) {
+    match difference {
+        symbol::Difference::Local(symbol) => (symbol, symbol::Direction::Remove),
+        symbol::Difference::Remote(symbol) => (symbol, symbol::Direction::Add),
     }
 }
 
 // used to combine two blocks of coded symbols generated from two distinct sets
+// The blocks must be the same length
 pub fn combine<T: symbol::Symbol>(
-    block_a: &Vec<symbol::CodedSymbol<T>>,
-    block_b: &Vec<symbol::CodedSymbol<T>>,
-) -> UnmanagedRatelessIBLT<T> {
-    let mut combined_block = Vec::new();
-
-    for (a, b) in block_a.iter().zip(block_b.iter()) {
-        combined_block.push(a.combine(b));
-    }
-    UnmanagedRatelessIBLT {
+    block_a: &CodedSymbolTable<T>,
+    block_b: &CodedSymbolTable<T>,
+) -> Result<UnmanagedRatelessIBLT<T>, Error> {
+    check_lengths(block_a, block_b)?;
+    let mut combined_block = block_a.clone();
+    combined_block.combine_from(block_b, 0..block_b.len());
+    Ok(UnmanagedRatelessIBLT {
         coded_symbols: combined_block,
-    }
+    })
 }
 
 // A collapsed block should effectively contain the difference between two blocks
+// The blocks must be the same length
 pub fn collapse<T: symbol::Symbol>(
-    block_local: &Vec<symbol::CodedSymbol<T>>,
-    block_remote: &Vec<symbol::CodedSymbol<T>>,
-) -> UnmanagedRatelessIBLT<T> {
-    let mut combined_block = Vec::new();
+    block_local: &CodedSymbolTable<T>,
+    block_remote: &CodedSymbolTable<T>,
+) -> Result<UnmanagedRatelessIBLT<T>, Error> {
+    check_lengths(block_local, block_remote)?;
+    let mut collapsed_block = block_local.clone();
+    collapsed_block.collapse_from(block_remote, 0..block_remote.len());
+    Ok(UnmanagedRatelessIBLT {
+        coded_symbols: collapsed_block,
+    })
+}
 
-    for (coded_symbol_local, coded_symbol_remote) in block_local.iter().zip(block_remote.iter()) {
-        combined_block.push(coded_symbol_local.collapse(coded_symbol_remote));
-    }
-    UnmanagedRatelessIBLT {
-        coded_symbols: combined_block,
+fn check_lengths<T: symbol::Symbol>(
+    block_a: &CodedSymbolTable<T>,
+    block_b: &CodedSymbolTable<T>,
+) -> Result<(), Error> {
+    if block_a.len() != block_b.len() {
+        return Err(Error::LengthMismatch {
+            left: block_a.len(),
+            right: block_b.len(),
+        });
     }
+    Ok(())
+}
+
+pub fn is_empty<T: symbol::Symbol>(block: &CodedSymbolTable<T>) -> bool {
+    (0..block.len()).all(|index| block.is_empty_at(index))
 }
 
-pub fn is_empty<T: symbol::Symbol>(block: &Vec<symbol::CodedSymbol<T>>) -> bool {
-    block.iter().all(|x| x.is_empty())
+/// The state of a block of coded symbols once we have peeled all the symbols that we can.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeStatus {
+    /// Every CodedSymbol is empty, all differences have been recovered.
+    Complete,
+    /// Peeling has stalled, but nothing looks wrong. Ask the remote for more coded symbols.
+    ///
+    /// 'pure_cells' is the number of CodedSymbols that could still be peeled (0 once we have
+    /// peeled everything we can) and 'nonzero_cells' is the number of CodedSymbols that still
+    /// hold at least one symbol.
+    NeedMoreSymbols {
+        pure_cells: usize,
+        nonzero_cells: usize,
+    },
+    /// The block can't be the difference of two sets of distinct symbols.
+    ///
+    /// This points at duplicates in one of the sets, the two sides using different hashing
+    /// or mappings, or corrupted coded symbols. More coded symbols will not help.
+    ///
+    /// 'index' is the first CodedSymbol found to be inconsistent.
+    Inconsistent { index: usize },
+}
+
+/// Classify a block of coded symbols, it is expected that this is called after peeling.
+///
+/// Every symbol is mapped to the CodedSymbol at index 0, so once that CodedSymbol is empty every
+/// other CodedSymbol must be empty too.
+///
+/// A CodedSymbol is also inconsistent if
+/// - it has a count of 0 and a hash of 0, but a non-zero sum
+/// - it looks pure, but the symbol it holds is not mapped to its index
+/// - peeling stopped at it, because the symbol it holds had already been peeled
+///
+/// An empty block (no coded symbols at all) needs more symbols, we don't know anything yet.
+pub fn decode_status<T: symbol::Symbol>(block: &CodedSymbolTable<T>) -> DecodeStatus {
+    if let Some(index) = block.peeled_again_at() {
+        return DecodeStatus::Inconsistent { index };
+    }
+    if block.is_empty() {
+        return DecodeStatus::NeedMoreSymbols {
+            pure_cells: 0,
+            nonzero_cells: 0,
+        };
+    }
+
+    let mut pure_cells = 0;
+    let mut nonzero_cells = 0;
+
+    for index in 0..block.len() {
+        if block.is_empty_at(index) {
+            if block.sum(index).iter().any(|&x| x != 0) {
+                return DecodeStatus::Inconsistent { index };
+            }
+            continue;
+        }
+
+        if block.is_empty_at(0) {
+            return DecodeStatus::Inconsistent { index };
+        }
+
+        if block.is_peelable(index) {
+            if peel_peek_at(block, index).is_none() {
+                return DecodeStatus::Inconsistent { index };
+            }
+            pure_cells += 1;
+        }
+        nonzero_cells += 1;
+    }
+
+    if nonzero_cells == 0 {
+        DecodeStatus::Complete
+    } else {
+        DecodeStatus::NeedMoreSymbols {
+            pure_cells,
+            nonzero_cells,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -350,34 +728,32 @@ mod tests {
         ]);
 
         let mut iblt_local = RatelessIBLT::new(items_local.clone());
-        iblt_local.extend_coded_symbols(0);
+        iblt_local.extend_coded_symbols(0).unwrap();
         let mut iblt_remote = RatelessIBLT::new(items_remote.clone());
-        iblt_remote.extend_coded_symbols(0);
+        iblt_remote.extend_coded_symbols(0).unwrap();
 
         let local_only: HashSet<SimpleSymbol> =
             items_local.difference(&items_remote).cloned().collect();
         let remote_only: HashSet<SimpleSymbol> =
             items_remote.difference(&items_local).cloned().collect();
 
-
-        let iblt_remote_unmanaged : UnmanagedRatelessIBLT<SimpleSymbol> = UnmanagedRatelessIBLT {
+        let iblt_remote_unmanaged: UnmanagedRatelessIBLT<SimpleSymbol> = UnmanagedRatelessIBLT {
             coded_symbols: iblt_remote.coded_symbols.clone(),
         };
 
-        let mut collapsed_local = iblt_local.collapse(&iblt_remote_unmanaged);
+        let mut collapsed_local = iblt_local.collapse(&iblt_remote_unmanaged).unwrap();
 
         let mut peeled_set_local = HashSet::new();
         let mut peeled_set_remote = HashSet::new();
 
         for s in collapsed_local.peel_all_symbols() {
             match s {
-                symbol::PeelableResult::Local(symbol) => {
+                symbol::Difference::Local(symbol) => {
                     peeled_set_local.insert(symbol.clone());
                 }
-                symbol::PeelableResult::Remote(symbol) => {
+                symbol::Difference::Remote(symbol) => {
                     peeled_set_remote.insert(symbol.clone());
                 }
-                symbol::PeelableResult::NotPeelable => panic!("Not expecting this case"),
             }
         }
         assert_eq!(local_only, peeled_set_local);
@@ -402,24 +778,478 @@ mod tests {
         // ]);
 
         let mut iblt = RatelessIBLT::new(items.clone());
-        iblt.extend_coded_symbols(0);
+        iblt.extend_coded_symbols(0).unwrap();
 
         let mut peeled_set = HashSet::new();
 
         for s in iblt.peel_all_symbols() {
             match s {
-                symbol::PeelableResult::Local(symbol) => {
+                symbol::Difference::Local(symbol) => {
                     peeled_set.insert(symbol.clone());
                 }
-                symbol::PeelableResult::Remote(_) => panic!("Not expecting remote symbol"),
-                symbol::PeelableResult::NotPeelable => panic!("Not expecting this case"),
+                symbol::Difference::Remote(_) => panic!("Not expecting remote symbol"),
             }
         }
 
-        assert!(iblt.is_empty());
+        assert!(iblt.is_empty().unwrap());
         assert_eq!(items, peeled_set);
     }
 
+    #[test]
+    fn test_peel_iter() {
+        use std::collections::HashSet;
+
+        let items_local: HashSet<SimpleSymbol> =
+            (0..20).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (5..25).map(|value| SimpleSymbol { value }).collect();
+
+        let mut iblt_local = RatelessIBLT::new(items_local);
+        let mut iblt_remote = RatelessIBLT::new(items_remote);
+        iblt_remote.extend_coded_symbols(0).unwrap();
+        let iblt_remote_unmanaged = UnmanagedRatelessIBLT {
+            coded_symbols: iblt_remote.coded_symbols.clone(),
+        };
+        let mut collapsed = iblt_local.collapse(&iblt_remote_unmanaged).unwrap();
+
+        // Taking a few symbols only peels that many
+        assert_eq!(collapsed.peel_iter().take(3).count(), 3);
+        assert!(!collapsed.is_empty());
+
+        let mut peeled_set_local = HashSet::new();
+        let mut peeled_set_remote = HashSet::new();
+        for s in collapsed.peel_iter() {
+            match s {
+                symbol::Difference::Local(symbol) => peeled_set_local.insert(symbol.value),
+                symbol::Difference::Remote(symbol) => peeled_set_remote.insert(symbol.value),
+            };
+        }
+        assert_eq!(peeled_set_local.len() + peeled_set_remote.len(), 7);
+        assert!(peeled_set_local.iter().all(|&x| x < 5));
+        assert!(peeled_set_remote.iter().all(|&x| x >= 20));
+        assert!(collapsed.is_empty());
+    }
+
+    #[test]
+    fn test_decode_status() {
+        use std::collections::HashSet;
+
+        let items_local: HashSet<SimpleSymbol> =
+            (0..20).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (5..25).map(|value| SimpleSymbol { value }).collect();
+
+        let mut iblt_local = RatelessIBLT::new(items_local);
+        let mut iblt_remote = RatelessIBLT::new(items_remote);
+        iblt_remote.extend_coded_symbols(0).unwrap();
+
+        // A handful of coded symbols is not enough to recover 10 differences
+        let mut too_short = UnmanagedRatelessIBLT::new();
+        for coded_symbol in iblt_remote.coded_symbols.iter().take(3) {
+            too_short.add_coded_symbol(&coded_symbol);
+        }
+        let mut collapsed = iblt_local.collapse(&too_short).unwrap();
+        collapsed.peel_all_symbols();
+        match collapsed.decode_status() {
+            DecodeStatus::NeedMoreSymbols {
+                pure_cells,
+                nonzero_cells,
+            } => {
+                assert_eq!(pure_cells, 0);
+                assert!(nonzero_cells > 0);
+            }
+            status => panic!("Not expecting {:?}", status),
+        }
+
+        let iblt_remote_unmanaged = UnmanagedRatelessIBLT {
+            coded_symbols: iblt_remote.coded_symbols.clone(),
+        };
+        let mut collapsed = iblt_local.collapse(&iblt_remote_unmanaged).unwrap();
+        assert_eq!(collapsed.peel_all_symbols().len(), 10);
+        assert_eq!(collapsed.decode_status(), DecodeStatus::Complete);
+
+        let empty: UnmanagedRatelessIBLT<SimpleSymbol> = UnmanagedRatelessIBLT::new();
+        assert_eq!(
+            empty.decode_status(),
+            DecodeStatus::NeedMoreSymbols {
+                pure_cells: 0,
+                nonzero_cells: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_status_inconsistent() {
+        let symbol = SimpleSymbol { value: 42 };
+
+        // A CodedSymbol that looks pure, placed at an index the symbol is not mapped to
+        let index = (1..)
+            .find(|&i| !mapping::RandomMapping::maps_to(&symbol, i))
+            .unwrap();
+        let mut forged = UnmanagedRatelessIBLT::new();
+        for i in 0..=index {
+            let mut coded_symbol = symbol::CodedSymbol::new();
+            if i == index {
+                coded_symbol.apply(&symbol, symbol::Direction::Add);
+            }
+            forged.add_coded_symbol(&coded_symbol);
+        }
+
+        // The forged CodedSymbol must not be peeled, as removing the symbol would not change it
+        assert!(forged.peel_all_symbols().is_empty());
+        assert_eq!(forged.decode_status(), DecodeStatus::Inconsistent { index });
+
+        // A zero count and hash with a non-zero sum can't happen without a collision
+        let mut coded_symbol = symbol::CodedSymbol::new();
+        coded_symbol.apply(&symbol, symbol::Direction::Add);
+        coded_symbol.count = 0;
+        coded_symbol.hash = 0;
+        let mut corrupted = UnmanagedRatelessIBLT::new();
+        corrupted.add_coded_symbol(&coded_symbol);
+        assert_eq!(
+            corrupted.decode_status(),
+            DecodeStatus::Inconsistent { index: 0 }
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_cached_prefix() {
+        use std::collections::HashSet;
+
+        let items: HashSet<SimpleSymbol> = (0..100).map(|value| SimpleSymbol { value }).collect();
+        let mut iblt = RatelessIBLT::new(items.clone());
+        iblt.extend_coded_symbols(2000).unwrap();
+
+        let mut saved = Vec::new();
+        iblt.save_prefix(&mut saved).unwrap();
+
+        let reloaded =
+            RatelessIBLT::with_cached_prefix(items.clone(), &mut saved.as_slice()).unwrap();
+        assert_eq!(reloaded.coded_symbols.len(), iblt.coded_symbols.len());
+        for (a, b) in reloaded.coded_symbols.iter().zip(iblt.coded_symbols.iter()) {
+            assert_eq!((&a.sum, a.hash, a.count), (&b.sum, b.hash, b.count));
+        }
+
+        // The set has changed since the prefix was saved
+        let mut changed_items = items.clone();
+        changed_items.insert(SimpleSymbol { value: 1000 });
+        assert!(matches!(
+            RatelessIBLT::with_cached_prefix(changed_items, &mut saved.as_slice()),
+            Err(Error::FingerprintMismatch)
+        ));
+
+        // Truncated or not a prefix at all
+        assert!(matches!(
+            RatelessIBLT::with_cached_prefix(items.clone(), &mut &saved[..saved.len() - 1]),
+            Err(Error::Io(_))
+        ));
+        assert!(matches!(
+            RatelessIBLT::with_cached_prefix(items, &mut &b"not a prefix"[..]),
+            Err(Error::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_collapse_in_place() {
+        use std::collections::HashSet;
+
+        let items_local: HashSet<SimpleSymbol> =
+            (0..50).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (3..53).map(|value| SimpleSymbol { value }).collect();
+
+        let mut iblt_local = RatelessIBLT::new(items_local);
+        let mut iblt_remote = RatelessIBLT::new(items_remote);
+        iblt_remote.extend_coded_symbols(0).unwrap();
+        let iblt_remote_unmanaged = UnmanagedRatelessIBLT {
+            coded_symbols: iblt_remote.coded_symbols.prefix(40),
+        };
+        let expected = iblt_local.collapse(&iblt_remote_unmanaged).unwrap();
+
+        // Collapsing the remote in batches, as it arrives
+        let mut collapsed = UnmanagedRatelessIBLT::new();
+        for start in (0..40).step_by(7) {
+            let end = usize::min(start + 7, 40);
+            iblt_local
+                .collapse_range(&iblt_remote_unmanaged, &mut collapsed, start..end)
+                .unwrap();
+        }
+        // And in one go, reusing the same UnmanagedRatelessIBLT
+        let mut collapsed_again = UnmanagedRatelessIBLT::new();
+        iblt_local
+            .collapse_into(&iblt_remote_unmanaged, &mut collapsed_again)
+            .unwrap();
+        iblt_local
+            .collapse_into(&iblt_remote_unmanaged, &mut collapsed_again)
+            .unwrap();
+
+        // Collapsing two unmanaged in place
+        let mut local_unmanaged = UnmanagedRatelessIBLT {
+            coded_symbols: iblt_local.coded_symbols.clone(),
+        };
+        assert!(matches!(
+            local_unmanaged.collapse_into(&iblt_remote_unmanaged),
+            Err(Error::LengthMismatch {
+                left: BLOCK_SIZE,
+                right: 40
+            })
+        ));
+        local_unmanaged.truncate_to(40);
+        local_unmanaged
+            .collapse_into(&iblt_remote_unmanaged)
+            .unwrap();
+
+        for result in [collapsed, collapsed_again, local_unmanaged] {
+            assert_eq!(result.coded_symbols.len(), 40);
+            for (a, b) in result
+                .coded_symbols
+                .iter()
+                .zip(expected.coded_symbols.iter())
+            {
+                assert_eq!((a.sum, a.hash, a.count), (b.sum, b.hash, b.count));
+            }
+        }
+    }
+
+    #[test]
+    fn test_mismatched_lengths() {
+        use std::collections::HashSet;
+
+        let items_a: HashSet<SimpleSymbol> = (0..10).map(|value| SimpleSymbol { value }).collect();
+        let items_b: HashSet<SimpleSymbol> = (10..20).map(|value| SimpleSymbol { value }).collect();
+        let mut iblt_a = RatelessIBLT::new(items_a);
+        let mut iblt_b = RatelessIBLT::new(items_b);
+
+        // Managed tables are extended to match exactly
+        iblt_b.extend_coded_symbols(1500).unwrap();
+        let combined = iblt_a.combine(&mut iblt_b).unwrap();
+        assert_eq!(combined.coded_symbols.len(), iblt_b.coded_symbols.len());
+
+        for len in [0, 1, 10, BLOCK_SIZE, BLOCK_SIZE + 1] {
+            let remote = UnmanagedRatelessIBLT {
+                coded_symbols: iblt_b.coded_symbols.prefix(len),
+            };
+            assert_eq!(iblt_a.collapse(&remote).unwrap().coded_symbols.len(), len);
+        }
+
+        // Unmanaged tables must match, or be truncated explicitly
+        let mut short = UnmanagedRatelessIBLT {
+            coded_symbols: iblt_a.coded_symbols.prefix(5),
+        };
+        let long = UnmanagedRatelessIBLT {
+            coded_symbols: iblt_b.coded_symbols.prefix(8),
+        };
+        assert!(matches!(
+            short.collapse(&long),
+            Err(Error::LengthMismatch { left: 5, right: 8 })
+        ));
+        assert!(matches!(
+            long.combine(&short),
+            Err(Error::LengthMismatch { left: 8, right: 5 })
+        ));
+        assert!(short.collapse_range(&long, 4..6).is_err());
+        short.collapse_range(&long, 0..5).unwrap();
+
+        let mut truncated = UnmanagedRatelessIBLT {
+            coded_symbols: long.coded_symbols.clone(),
+        };
+        truncated.truncate_to(5);
+        assert_eq!(truncated.collapse(&short).unwrap().coded_symbols.len(), 5);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    proptest::proptest! {
+        // Collapse every prefix of the remote's coded symbols against the local, and peel.
+        // Whatever is peeled must be part of the difference with the right label, and once the
+        // collapsed prefix is empty we must have peeled exactly the difference.
+        #[test]
+        fn prop_collapse_then_peel_every_prefix(
+            common in proptest::collection::hash_set(0u64..10_000, 0..50),
+            local_only in proptest::collection::hash_set(10_000u64..20_000, 0..8),
+            remote_only in proptest::collection::hash_set(20_000u64..30_000, 0..8),
+        ) {
+            use std::collections::HashSet;
+
+            let to_symbols = |values: &HashSet<u64>| -> Vec<SimpleSymbol> {
+                values.iter().map(|&value| SimpleSymbol { value }).collect()
+            };
+            let items_local: Vec<SimpleSymbol> =
+                to_symbols(&common).into_iter().chain(to_symbols(&local_only)).collect();
+            let items_remote: Vec<SimpleSymbol> =
+                to_symbols(&common).into_iter().chain(to_symbols(&remote_only)).collect();
+
+            let mut iblt_local = RatelessIBLT::new(items_local);
+            let mut iblt_remote = RatelessIBLT::new(items_remote);
+            iblt_remote.extend_coded_symbols(0).unwrap();
+
+            let mut decoded = false;
+            for len in 1..=100 {
+                let remote = UnmanagedRatelessIBLT {
+                    coded_symbols: iblt_remote.coded_symbols.prefix(len),
+                };
+                let mut collapsed = iblt_local.collapse(&remote).unwrap();
+                proptest::prop_assert_eq!(collapsed.coded_symbols.len(), len);
+
+                let (peeled_local, peeled_remote) =
+                    symbol::split_differences(collapsed.peel_all_symbols());
+                let peeled_local: HashSet<u64> = peeled_local.iter().map(|s| s.value).collect();
+                let peeled_remote: HashSet<u64> = peeled_remote.iter().map(|s| s.value).collect();
+                proptest::prop_assert!(peeled_local.is_subset(&local_only));
+                proptest::prop_assert!(peeled_remote.is_subset(&remote_only));
+                let inconsistent =
+                    matches!(collapsed.decode_status(), DecodeStatus::Inconsistent { .. });
+                proptest::prop_assert!(!inconsistent);
+
+                if collapsed.is_empty() {
+                    proptest::prop_assert_eq!(&peeled_local, &local_only);
+                    proptest::prop_assert_eq!(&peeled_remote, &remote_only);
+                    decoded = true;
+                }
+            }
+            // 100 coded symbols is plenty for a difference of at most 16
+            proptest::prop_assert!(decoded);
+        }
+    }
+
+    // Only coded symbol 0 holds the symbol, the other indexes it maps to are empty. Peeling it
+    // leaves a pure remote copy at the next index, peeling that puts it back at 0, and so on.
+    fn forged_table(len: usize) -> UnmanagedRatelessIBLT<SimpleSymbol> {
+        let mut forged = UnmanagedRatelessIBLT::new();
+        let mut first = symbol::CodedSymbol::new();
+        first.apply(&SimpleSymbol { value: 1 }, symbol::Direction::Add);
+        forged.add_coded_symbol(&first);
+        for _ in 1..len {
+            forged.add_coded_symbol(&symbol::CodedSymbol::new());
+        }
+        forged
+    }
+
+    #[test]
+    fn test_forged_pure_coded_symbol() {
+        // Peeling the symbol at index 0 leaves it pure (with the opposite sign) everywhere else
+        // it maps to, so it would be peeled again and again
+        let mut forged = forged_table(20);
+        assert!(forged.peel_all_symbols().len() <= 1);
+        assert!(!forged.is_empty());
+        assert!(matches!(
+            forged.decode_status(),
+            DecodeStatus::Inconsistent { .. }
+        ));
+        // and it stays stopped
+        assert!(forged.peel_all_symbols().is_empty());
+
+        let mut decoder = crate::Decoder::new();
+        for index in 0..20 {
+            decoder.add_coded_symbol(&forged_table(20).coded_symbols.get(index));
+        }
+        assert!(decoder.recovered_so_far().len() <= 1);
+        assert!(matches!(
+            decoder.decode_status(),
+            DecodeStatus::Inconsistent { .. }
+        ));
+    }
+
+    #[test]
+    fn test_forged_extreme_counts() {
+        let mut local = RatelessIBLT::new((0..10).map(|value| SimpleSymbol { value }));
+        for count in [i64::MAX, i64::MIN, -1, 1] {
+            let mut forged = UnmanagedRatelessIBLT::new();
+            for _ in 0..10 {
+                let mut coded_symbol = symbol::CodedSymbol::new();
+                coded_symbol.count = count;
+                coded_symbol.hash = u64::MAX;
+                forged.add_coded_symbol(&coded_symbol);
+            }
+            let mut collapsed = local.collapse(&forged).unwrap();
+            assert!(collapsed.peel_all_symbols().len() <= 10);
+            let mut combined = forged.combine(&forged).unwrap();
+            assert!(combined.peel_all_symbols().len() <= 10);
+            collapsed.decode_status();
+            forged.collapse(&combined).unwrap().decode_status();
+        }
+    }
+
+    proptest::proptest! {
+        // Whatever a remote sends, peeling stops after at most one peel per coded symbol
+        #[test]
+        fn prop_peeling_forged_coded_symbols_terminates(
+            forged in proptest::collection::vec(
+                (0u64..4, proptest::prelude::any::<bool>(), -2i64..=2),
+                1..64,
+            ),
+        ) {
+            let mut table = UnmanagedRatelessIBLT::<SimpleSymbol>::new();
+            let mut decoder = crate::Decoder::new();
+            for (value, hashed, count) in forged {
+                let mut coded_symbol = symbol::CodedSymbol::new();
+                coded_symbol.apply(&SimpleSymbol { value }, symbol::Direction::Add);
+                if !hashed {
+                    coded_symbol.hash = 0;
+                }
+                coded_symbol.count = count;
+                table.add_coded_symbol(&coded_symbol);
+                decoder.add_coded_symbol(&coded_symbol);
+            }
+            let len = table.coded_symbols.len();
+            proptest::prop_assert!(table.peel_all_symbols().len() <= len);
+            proptest::prop_assert!(decoder.recovered_so_far().len() <= len);
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_tables() {
+        use std::collections::HashSet;
+
+        let dir = std::env::temp_dir().join(format!("riblt-encoder-{}", std::process::id()));
+        let mapped = |name: &str| {
+            let path = dir.join(name);
+            std::fs::create_dir_all(&path).unwrap();
+            CodedSymbolTable::with_mmap_dir(path).unwrap()
+        };
+
+        let items_local: HashSet<SimpleSymbol> =
+            (0..200).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (10..220).map(|value| SimpleSymbol { value }).collect();
+        let mut iblt_local = RatelessIBLT::with_table(items_local, mapped("local"));
+        let mut iblt_remote = RatelessIBLT::with_table(items_remote, mapped("remote"));
+        iblt_remote.extend_coded_symbols(99).unwrap();
+        assert!(iblt_remote.coded_symbols.is_mapped());
+
+        // The remote's coded symbols arrive into a mapped table, and are collapsed into another
+        let mut received = UnmanagedRatelessIBLT::with_table(mapped("received"));
+        for coded_symbol in iblt_remote.coded_symbols.iter().take(100) {
+            received.add_coded_symbol(&coded_symbol);
+        }
+        let mut collapsed = UnmanagedRatelessIBLT::with_table(mapped("collapsed"));
+        iblt_local.collapse_into(&received, &mut collapsed).unwrap();
+        assert!(iblt_local.coded_symbols.is_mapped());
+        assert!(collapsed.coded_symbols.is_mapped());
+        assert_eq!(collapsed.coded_symbols.len(), 100);
+
+        // Collapsing two unmanaged tables in place keeps ours mapped too
+        let mut in_place = UnmanagedRatelessIBLT::with_table(mapped("in_place"));
+        for coded_symbol in iblt_local.coded_symbols.iter().take(100) {
+            in_place.add_coded_symbol(&coded_symbol);
+        }
+        in_place.collapse_into(&received).unwrap();
+        assert!(in_place.coded_symbols.is_mapped());
+
+        for mut result in [collapsed, in_place] {
+            let (local, remote) = symbol::split_differences(result.peel_all_symbols());
+            let local: HashSet<u64> = local.iter().map(|s| s.value).collect();
+            let remote: HashSet<u64> = remote.iter().map(|s| s.value).collect();
+            assert_eq!(local, (0..10).collect());
+            assert_eq!(remote, (200..220).collect());
+            assert!(result.is_empty());
+        }
+
+        drop((iblt_local, iblt_remote, received));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_union() {
         use std::collections::HashSet;
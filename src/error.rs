@@ -0,0 +1,98 @@
+use alloc::boxed::Box;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// Errors returned by the fallible parts of this crate.
+///
+/// Building, collapsing and peeling coded symbols in memory can't fail, but reading coded
+/// symbols from somewhere else can.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing failed
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// Scanning the set failed, this holds the SymbolSource::Error
+    Source(Box<dyn core::error::Error + Send + Sync>),
+    /// The data is not in the format we expected, e.g. a bad magic number or version
+    InvalidFormat(&'static str),
+    /// The coded symbols were produced for symbols of a different BYTE_ARRAY_LENGTH
+    SymbolLengthMismatch { expected: usize, found: usize },
+    /// The coded symbols were produced with a different Symbol::HASH_SCHEME
+    HashSchemeMismatch { expected: u32, found: u32 },
+    /// The coded symbols were produced from a different set
+    FingerprintMismatch,
+    /// The coded symbols were produced with a different time window, see RatelessIBLT::with_window
+    CutoffMismatch {
+        expected: Option<u64>,
+        found: Option<u64>,
+    },
+    /// The epoch has expired, or hasn't started yet, see AntiEntropy
+    UnknownEpoch { epoch: u64 },
+    /// There is no shard with this index, see ShardedRatelessIBLT
+    UnknownShard { shard: usize },
+    /// Two sequences of coded symbols that must be the same length are not.
+    ///
+    /// Use UnmanagedRatelessIBLT::truncate_to to explicitly drop the extra coded symbols.
+    LengthMismatch { left: usize, right: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Source(err) => write!(f, "scanning the set failed: {}", err),
+            Error::InvalidFormat(reason) => write!(f, "invalid format: {}", reason),
+            Error::SymbolLengthMismatch { expected, found } => write!(
+                f,
+                "symbol length mismatch: expected {} bytes, found {} bytes",
+                expected, found
+            ),
+            Error::HashSchemeMismatch { expected, found } => write!(
+                f,
+                "hash scheme mismatch: expected {}, found {}",
+                expected, found
+            ),
+            Error::FingerprintMismatch => {
+                write!(f, "the coded symbols were not produced from this set")
+            }
+            Error::CutoffMismatch { expected, found } => write!(
+                f,
+                "cutoff mismatch: expected {:?}, found {:?}",
+                expected, found
+            ),
+            Error::UnknownEpoch { epoch } => write!(f, "unknown epoch {}", epoch),
+            Error::UnknownShard { shard } => write!(f, "unknown shard {}", shard),
+            Error::LengthMismatch { left, right } => write!(
+                f,
+                "length mismatch: {} coded symbols against {} coded symbols",
+                left, right
+            ),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Io(err) => Some(err),
+            Error::Source(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    pub(crate) fn from_source<E: core::error::Error + Send + Sync + 'static>(err: E) -> Self {
+        Error::Source(Box::new(err))
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -1,10 +1,52 @@
+//! Without the default std feature the crate is no_std and only needs alloc. Everything that
+//! works in memory (encoding, collapsing, peeling, the Decoder) is still available, the parts
+//! that read or write files and streams are not.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod anti_entropy;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod decoder;
+#[cfg(any(feature = "capi", feature = "wasm-bindgen", feature = "python", test))]
+mod dynamic;
 mod encoder;
+mod error;
 mod mapping;
+#[cfg(feature = "std")]
+mod protocol;
+#[cfg(feature = "python")]
+pub mod python;
+mod sharded;
+mod source;
 mod symbol;
+mod table;
+mod wire;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
 
-pub use encoder::{RatelessIBLT, UnmanagedRatelessIBLT};
+pub use anti_entropy::{AntiEntropy, AntiEntropyConfig};
+pub use decoder::Decoder;
+pub use encoder::{DecodeStatus, RatelessIBLT, UnmanagedRatelessIBLT};
+pub use error::Error;
 pub use mapping::RandomMapping;
-pub use symbol::{Symbol, CodedSymbol};
+#[cfg(feature = "std")]
+pub use protocol::Header;
+pub use sharded::{shard_of, ShardedRatelessIBLT};
+#[cfg(feature = "std")]
+pub use source::FileSymbolSource;
+pub use source::{SymbolSource, Windowed};
+#[cfg(feature = "std")]
+pub use symbol::split_differences_into_sets;
+pub use symbol::{
+    split_differences, ByteSymbol, CodedSymbol, Difference, Fingerprint, Symbol,
+    TimestampedSymbol,
+};
+pub use table::CodedSymbolTable;
+pub use wire::{decode_coded_symbol, encode_coded_symbol, encoded_len};
+#[cfg(feature = "std")]
+pub use wire::{read_coded_symbol, write_coded_symbol};
 
 #[cfg(test)]
 pub mod test_helpers {
@@ -30,4 +72,11 @@ pub mod test_helpers {
             SimpleSymbol { value }
         }
     }
+
+    // In tests the value doubles as the insert time
+    impl symbol::TimestampedSymbol for SimpleSymbol {
+        fn inserted_at(&self) -> u64 {
+            self.value
+        }
+    }
 }
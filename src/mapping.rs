@@ -1,5 +1,4 @@
 use crate::symbol::Symbol;
-use std::f64;
 
 /// A deterministic pseudo random mapping from a symbol to a sequence of indexes.
 ///
@@ -23,20 +22,46 @@ impl Iterator for RandomMapping {
         let tp32: f64 = (1u64 << 32) as f64;
 
         // diff to next index
-        let diff = (self.last_idx as f64 + 1.5) * (tp32 / (r as f64 + 1.0).sqrt() - 1.0);
+        let diff = (self.last_idx as f64 + 1.5) * (tp32 / sqrt(r as f64 + 1.0) - 1.0);
 
         let index_to_return = self.last_idx;
-        self.last_idx += diff.ceil() as u64;
+        // The gaps grow quickly, after a hundred or so indexes they no longer fit in a u64.
+        // Every index from then on is u64::MAX, far past the end of any block of coded symbols.
+        self.last_idx = self.last_idx.saturating_add(ceil(diff) as u64);
 
-        Some(index_to_return as usize)
+        Some(usize::try_from(index_to_return).unwrap_or(usize::MAX))
     }
 }
 
+// sqrt and ceil are exact in IEEE 754 and in libm, so peers with and without std produce the
+// same indexes. The f64 methods are used when we have them as they compile to single instructions.
+#[cfg(feature = "std")]
+fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+#[cfg(feature = "std")]
+fn ceil(x: f64) -> f64 {
+    x.ceil()
+}
+
+#[cfg(not(feature = "std"))]
+use libm::{ceil, sqrt};
+
 impl RandomMapping {
     pub fn new<T: Symbol>(given_symbol: &T) -> Self {
         let prng = given_symbol.hash_();
         RandomMapping { prng, last_idx: 0 }
     }
+
+    /// Checks if the given symbol is encoded into the CodedSymbol at the provided index.
+    ///
+    /// Indexes are produced in increasing order, so we only need to walk the mapping up to the index.
+    pub fn maps_to<T: Symbol>(given_symbol: &T, index: usize) -> bool {
+        RandomMapping::new(given_symbol)
+            .take_while(|&x| x <= index)
+            .any(|x| x == index)
+    }
 }
 
 #[cfg(test)]
@@ -65,4 +90,48 @@ mod tests {
         println!("{:?}", below_100);
         // assert!(false);
     }
+
+    #[test]
+    fn test_libm_matches_std() {
+        // the no_std build uses libm, the indexes have to be the same or the peers can't decode
+        for value in 0..1000 {
+            let mut prng = SimpleSymbol { value }.hash_();
+            let mut last_idx = 0u64;
+            let with_libm: Vec<usize> = (0..50)
+                .map(|_| {
+                    prng = prng.wrapping_mul(0xda942042e4dd58b5);
+                    let tp32 = (1u64 << 32) as f64;
+                    let step = tp32 / libm::sqrt(prng as f64 + 1.0) - 1.0;
+                    let diff = (last_idx as f64 + 1.5) * step;
+                    let index = last_idx;
+                    last_idx += libm::ceil(diff) as u64;
+                    index as usize
+                })
+                .collect();
+            let mapping = RandomMapping::new(&SimpleSymbol { value }).take(50);
+            assert_eq!(mapping.collect::<Vec<_>>(), with_libm);
+        }
+    }
+
+    #[test]
+    fn test_mapping_saturates() {
+        for value in 0..100 {
+            let indexes: Vec<usize> = RandomMapping::new(&SimpleSymbol { value })
+                .take(1000)
+                .collect();
+            assert!(indexes.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert_eq!(indexes[999], usize::MAX);
+        }
+    }
+
+    #[test]
+    fn test_maps_to() {
+        let symbol = SimpleSymbol { value: 3 };
+        let indexes: Vec<usize> = RandomMapping::new(&symbol).take(10).collect();
+
+        assert!(RandomMapping::maps_to(&symbol, 0));
+        for index in 0..=indexes[9] {
+            assert_eq!(RandomMapping::maps_to(&symbol, index), indexes.contains(&index));
+        }
+    }
 }
@@ -0,0 +1,256 @@
+use crate::error::Error;
+use crate::symbol;
+use crate::wire;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+const HEADER_MAGIC: &[u8; 8] = b"RIBLTHDR";
+const HEADER_VERSION: u32 = 2;
+
+/// Sent by the side streaming coded symbols, before the first coded symbol.
+///
+/// The receiving side checks that it can make sense of the coded symbols that follow (same symbol
+/// length and Symbol::HASH_SCHEME), and builds its own RatelessIBLT over the same time window.
+/// If either side disagrees, collapsing would produce garbage rather than the difference.
+///
+/// If the header carries the sender's Fingerprint and it matches ours (see in_sync_with), the
+/// sets are the same and the receiver can stop without reading a single coded symbol.
+///
+/// Like CodedSymbol, the Header implements serde's Serialize if you would rather use a
+/// serialization library than write/read.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub symbol_length: u64,
+    pub hash_scheme: u32,
+    /// The cutoff passed to RatelessIBLT::with_window, None if every symbol in the set is encoded
+    pub cutoff: Option<u64>,
+    /// The fingerprint of the sender's set (within the cutoff), see RatelessIBLT::fingerprint
+    pub fingerprint: Option<symbol::Fingerprint>,
+}
+
+impl Header {
+    /// A header for coded symbols of T, over the whole set
+    pub fn new<T: symbol::Symbol>() -> Self {
+        Header {
+            version: HEADER_VERSION,
+            symbol_length: T::BYTE_ARRAY_LENGTH as u64,
+            hash_scheme: T::HASH_SCHEME,
+            cutoff: None,
+            fingerprint: None,
+        }
+    }
+
+    /// Only the symbols inserted at or before the cutoff are encoded
+    pub fn with_cutoff(mut self, cutoff: u64) -> Self {
+        self.cutoff = Some(cutoff);
+        self
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: symbol::Fingerprint) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// true if the sender's set has the same fingerprint as ours, so there is no need to stream
+    /// any coded symbols. Always false if the sender didn't include a fingerprint.
+    pub fn in_sync_with(&self, fingerprint: &symbol::Fingerprint) -> bool {
+        self.fingerprint.as_ref() == Some(fingerprint)
+    }
+
+    /// Check the coded symbols following this header can be collapsed against our own coded
+    /// symbols of T, built with the provided cutoff.
+    pub fn check<T: symbol::Symbol>(&self, cutoff: Option<u64>) -> Result<(), Error> {
+        if self.version != HEADER_VERSION {
+            return Err(Error::InvalidFormat("unsupported header version"));
+        }
+        if self.symbol_length != T::BYTE_ARRAY_LENGTH as u64 {
+            return Err(Error::SymbolLengthMismatch {
+                expected: T::BYTE_ARRAY_LENGTH,
+                found: self.symbol_length as usize,
+            });
+        }
+        if self.hash_scheme != T::HASH_SCHEME {
+            return Err(Error::HashSchemeMismatch {
+                expected: T::HASH_SCHEME,
+                found: self.hash_scheme,
+            });
+        }
+        if self.cutoff != cutoff {
+            return Err(Error::CutoffMismatch {
+                expected: cutoff,
+                found: self.cutoff,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write the header in a fixed size, platform independent layout
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_all(HEADER_MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&self.symbol_length.to_le_bytes())?;
+        writer.write_all(&self.hash_scheme.to_le_bytes())?;
+        writer.write_all(&[self.cutoff.is_some() as u8])?;
+        writer.write_all(&self.cutoff.unwrap_or(0).to_le_bytes())?;
+        let fingerprint = self.fingerprint.unwrap_or_default();
+        writer.write_all(&[self.fingerprint.is_some() as u8])?;
+        writer.write_all(&fingerprint.hash.to_le_bytes())?;
+        writer.write_all(&fingerprint.count.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Read a header written by write.
+    ///
+    /// Only the magic and version are checked here, use check to compare it against our own.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let magic: [u8; 8] = wire::read_array(reader)?;
+        if &magic != HEADER_MAGIC {
+            return Err(Error::InvalidFormat("not a RIBLT header"));
+        }
+        let version = u32::from_le_bytes(wire::read_array(reader)?);
+        if version != HEADER_VERSION {
+            return Err(Error::InvalidFormat("unsupported header version"));
+        }
+        let symbol_length = u64::from_le_bytes(wire::read_array(reader)?);
+        let hash_scheme = u32::from_le_bytes(wire::read_array(reader)?);
+        let [has_cutoff] = wire::read_array(reader)?;
+        let cutoff = u64::from_le_bytes(wire::read_array(reader)?);
+        let cutoff = match has_cutoff {
+            0 => None,
+            1 => Some(cutoff),
+            _ => return Err(Error::InvalidFormat("bad cutoff flag")),
+        };
+        let [has_fingerprint] = wire::read_array(reader)?;
+        let fingerprint = symbol::Fingerprint {
+            hash: u64::from_le_bytes(wire::read_array(reader)?),
+            count: i64::from_le_bytes(wire::read_array(reader)?),
+        };
+        let fingerprint = match has_fingerprint {
+            0 => None,
+            1 => Some(fingerprint),
+            _ => return Err(Error::InvalidFormat("bad fingerprint flag")),
+        };
+        Ok(Header {
+            version,
+            symbol_length,
+            hash_scheme,
+            cutoff,
+            fingerprint,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::RatelessIBLT;
+    use crate::symbol::Symbol;
+    use crate::test_helpers::SimpleSymbol;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_header_round_trip() {
+        for header in [
+            Header::new::<SimpleSymbol>(),
+            Header::new::<SimpleSymbol>().with_cutoff(1_700_000_000),
+            Header::new::<SimpleSymbol>().with_fingerprint(symbol::Fingerprint {
+                hash: 0xdeadbeef,
+                count: -3,
+            }),
+        ] {
+            let mut buffer = Vec::new();
+            header.write(&mut buffer).unwrap();
+            let read = Header::read(&mut buffer.as_slice()).unwrap();
+            assert_eq!(read, header);
+            read.check::<SimpleSymbol>(header.cutoff).unwrap();
+        }
+
+        assert!(matches!(
+            Header::read(&mut &b"RIBLTPFX"[..]),
+            Err(Error::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_check() {
+        let header = Header::new::<SimpleSymbol>().with_cutoff(10);
+        assert!(matches!(
+            header.check::<SimpleSymbol>(None),
+            Err(Error::CutoffMismatch {
+                expected: None,
+                found: Some(10)
+            })
+        ));
+
+        let mut header = Header::new::<SimpleSymbol>();
+        header.symbol_length = 16;
+        assert!(matches!(
+            header.check::<SimpleSymbol>(None),
+            Err(Error::SymbolLengthMismatch {
+                expected: 8,
+                found: 16
+            })
+        ));
+
+        let mut header = Header::new::<SimpleSymbol>();
+        header.hash_scheme = SimpleSymbol::HASH_SCHEME + 1;
+        assert!(matches!(
+            header.check::<SimpleSymbol>(None),
+            Err(Error::HashSchemeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_skip_when_in_sync() {
+        let items: Vec<SimpleSymbol> = (0..500).map(|value| SimpleSymbol { value }).collect();
+        let mut iblt_remote = RatelessIBLT::new(items.clone());
+        let iblt_local = RatelessIBLT::new(items[1..].to_vec());
+
+        // Scanned before any coded symbols are generated, and read from index 0 afterwards
+        let fingerprint = iblt_remote.fingerprint().unwrap();
+        assert_eq!(fingerprint.count, 500);
+        let first = iblt_remote.get_coded_symbol(0).unwrap();
+        assert_eq!(symbol::Fingerprint::from(&first), fingerprint);
+        // Peeling the managed coded symbols doesn't change the fingerprint
+        iblt_remote.peel_all_symbols();
+        assert_eq!(iblt_remote.fingerprint().unwrap(), fingerprint);
+
+        let header = Header::new::<SimpleSymbol>().with_fingerprint(fingerprint);
+        assert!(header.in_sync_with(&RatelessIBLT::new(items).fingerprint().unwrap()));
+        assert!(!header.in_sync_with(&iblt_local.fingerprint().unwrap()));
+        assert!(!Header::new::<SimpleSymbol>().in_sync_with(&fingerprint));
+    }
+
+    #[test]
+    fn test_windowed_reconciliation() {
+        // Both sides agree on everything inserted up to 1000, but the remote has already seen
+        // newer inserts that are still being gossiped to us
+        let items_local: HashSet<SimpleSymbol> =
+            (5..1100).map(|value| SimpleSymbol { value }).collect();
+        let items_remote: HashSet<SimpleSymbol> =
+            (0..1200).map(|value| SimpleSymbol { value }).collect();
+
+        // The remote sends its header, we build over the same window
+        let mut iblt_remote = RatelessIBLT::with_window(items_remote, 1000);
+        let mut buffer = Vec::new();
+        Header::new::<SimpleSymbol>()
+            .with_cutoff(iblt_remote.cutoff())
+            .write(&mut buffer)
+            .unwrap();
+        let header = Header::read(&mut buffer.as_slice()).unwrap();
+        let mut iblt_local = RatelessIBLT::with_window(items_local, header.cutoff.unwrap());
+        header.check::<SimpleSymbol>(Some(iblt_local.cutoff())).unwrap();
+
+        let mut remote = crate::UnmanagedRatelessIBLT::new();
+        for index in 0..100 {
+            remote.add_coded_symbol(&iblt_remote.get_coded_symbol(index).unwrap());
+        }
+        let mut collapsed = iblt_local.collapse(&remote).unwrap();
+        let (local, remote) = symbol::split_differences(collapsed.peel_all_symbols());
+        assert!(collapsed.is_empty());
+        assert!(local.is_empty());
+        let remote: HashSet<u64> = remote.iter().map(|s| s.value).collect();
+        assert_eq!(remote, (0..5).collect());
+    }
+}
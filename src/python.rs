@@ -0,0 +1,272 @@
+//! Python bindings, built with the python feature (e.g. `maturin develop --features python`).
+//!
+//! Symbols are bytes objects, all of the same width. Coded symbols are bytes in the wire format
+//! (see write_coded_symbol), decode_coded_symbol splits one into its sum, hash and count.
+//!
+//! ```python
+//! import riblt
+//!
+//! local = riblt.Encoder(local_ids)
+//! remote = riblt.Encoder(remote_ids)
+//! decoder = riblt.Decoder(local.width)
+//! while len(decoder) == 0 or not decoder.is_empty():
+//!     for index, cs in enumerate(remote.coded_symbols(len(decoder), len(decoder) + 10)):
+//!         decoder.add(local.collapse_coded_symbol(len(decoder), cs))
+//! local_only, remote_only = decoder.peel()
+//! ```
+
+use crate::dynamic::{
+    coded_symbol_len, AnyDecoder, AnyEncoder, ConcatenatedSymbols, SUPPORTED_WIDTHS,
+};
+use crate::error::Error;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+fn unsupported_width(width: usize) -> PyErr {
+    PyValueError::new_err(format!(
+        "symbols of {} bytes are not supported, use one of {:?}",
+        width, SUPPORTED_WIDTHS
+    ))
+}
+
+/// Generates coded symbols for a set of bytes objects.
+///
+/// The width is taken from the first item, every other item must be the same length. Pass width
+/// for an empty set.
+#[pyclass(module = "riblt")]
+pub struct Encoder {
+    inner: AnyEncoder<ConcatenatedSymbols>,
+    width: usize,
+}
+
+#[pymethods]
+impl Encoder {
+    #[new]
+    #[pyo3(signature = (items, width = None))]
+    fn new(items: Vec<Bound<'_, PyBytes>>, width: Option<usize>) -> PyResult<Self> {
+        let width = match (width, items.first()) {
+            (Some(width), _) => width,
+            (None, Some(first)) => first.as_bytes().len(),
+            (None, None) => {
+                return Err(PyValueError::new_err(
+                    "can't infer the width of an empty set, pass width",
+                ))
+            }
+        };
+
+        let mut symbols = Vec::with_capacity(items.len() * width);
+        for (index, item) in items.iter().enumerate() {
+            let item = item.as_bytes();
+            if item.len() != width {
+                return Err(PyValueError::new_err(format!(
+                    "item {} is {} bytes, expected {}",
+                    index,
+                    item.len(),
+                    width
+                )));
+            }
+            symbols.extend_from_slice(item);
+        }
+
+        let inner = AnyEncoder::new(width, ConcatenatedSymbols(symbols))
+            .ok_or_else(|| unsupported_width(width))?;
+        Ok(Encoder { inner, width })
+    }
+
+    /// The width of the symbols, in bytes
+    #[getter]
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The coded symbols from start up to (not including) end, as bytes in the wire format
+    fn coded_symbols(&mut self, start: usize, end: usize) -> PyResult<Vec<Vec<u8>>> {
+        (start..end)
+            .map(|index| Ok(self.inner.get_coded_symbol(index)?))
+            .collect()
+    }
+
+    /// Our coded symbol at the index minus the remote's, ready for Decoder.add
+    fn collapse_coded_symbol(&mut self, index: usize, remote: &[u8]) -> PyResult<Vec<u8>> {
+        Ok(self.inner.collapse_coded_symbol(index, remote)?)
+    }
+
+    /// The (hash, count) fingerprint of the set, if two sets have the same one they are the same
+    fn fingerprint(&self) -> PyResult<(u64, i64)> {
+        let fingerprint = self.inner.fingerprint()?;
+        Ok((fingerprint.hash, fingerprint.count))
+    }
+}
+
+/// Peels the difference out of collapsed coded symbols, added in order.
+#[pyclass(module = "riblt")]
+pub struct Decoder {
+    inner: AnyDecoder,
+    local_only: Vec<Vec<u8>>,
+    remote_only: Vec<Vec<u8>>,
+}
+
+#[pymethods]
+impl Decoder {
+    #[new]
+    fn new(width: usize) -> PyResult<Self> {
+        let inner = AnyDecoder::new(width).ok_or_else(|| unsupported_width(width))?;
+        Ok(Decoder {
+            inner,
+            local_only: Vec::new(),
+            remote_only: Vec::new(),
+        })
+    }
+
+    /// Add the next collapsed coded symbol (see Encoder.collapse_coded_symbol)
+    fn add(&mut self, coded_symbol: &[u8]) -> PyResult<()> {
+        Ok(self.inner.add_coded_symbol(coded_symbol)?)
+    }
+
+    /// Everything peeled so far, as (local_only, remote_only) lists of bytes.
+    ///
+    /// This is the whole difference once is_empty() returns True.
+    fn peel(&mut self) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        while let Some(difference) = self.inner.next_difference() {
+            match difference.local {
+                true => self.local_only.push(difference.symbol),
+                false => self.remote_only.push(difference.symbol),
+            }
+        }
+        (self.local_only.clone(), self.remote_only.clone())
+    }
+
+    /// True once every coded symbol added is empty, so the whole difference has been peeled
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// The width of the symbols, in bytes
+    #[getter]
+    fn width(&self) -> usize {
+        self.inner.width()
+    }
+
+    /// The number of coded symbols added, which is also the index of the next one to add
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+/// Split a coded symbol in the wire format into its (sum, hash, count)
+#[pyfunction]
+fn decode_coded_symbol(coded_symbol: &[u8], width: usize) -> PyResult<(Vec<u8>, u64, i64)> {
+    if coded_symbol.len() != coded_symbol_len(width) {
+        return Err(Error::InvalidFormat("coded symbol has the wrong length").into());
+    }
+    let (sum, rest) = coded_symbol.split_at(width);
+    let (hash, count) = rest.split_at(8);
+    Ok((
+        sum.to_vec(),
+        u64::from_le_bytes(hash.try_into().unwrap()),
+        i64::from_le_bytes(count.try_into().unwrap()),
+    ))
+}
+
+/// The opposite of decode_coded_symbol
+#[pyfunction]
+fn encode_coded_symbol(sum: &[u8], hash: u64, count: i64) -> Vec<u8> {
+    let mut coded_symbol = Vec::with_capacity(coded_symbol_len(sum.len()));
+    coded_symbol.extend_from_slice(sum);
+    coded_symbol.extend_from_slice(&hash.to_le_bytes());
+    coded_symbol.extend_from_slice(&count.to_le_bytes());
+    coded_symbol
+}
+
+#[pymodule]
+fn riblt(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Encoder>()?;
+    m.add_class::<Decoder>()?;
+    m.add_function(wrap_pyfunction!(decode_coded_symbol, m)?)?;
+    m.add_function(wrap_pyfunction!(encode_coded_symbol, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    fn run(script: &str) {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = pyo3::wrap_pymodule!(riblt)(py);
+            let globals = PyDict::new(py);
+            globals.set_item("riblt", module).unwrap();
+            let script = std::ffi::CString::new(script).unwrap();
+            if let Err(err) = py.run(&script, Some(&globals), None) {
+                // print the traceback
+                err.display(py);
+                panic!("the script raised {}", err);
+            }
+        });
+    }
+
+    #[test]
+    fn test_reconcile() {
+        run(r#"
+local_ids = [i.to_bytes(8, "little") for i in range(100)]
+remote_ids = [i.to_bytes(8, "little") for i in range(3, 102)]
+local = riblt.Encoder(local_ids)
+remote = riblt.Encoder(remote_ids)
+assert local.width == 8
+assert local.fingerprint() != remote.fingerprint()
+assert remote.fingerprint()[1] == 99
+
+decoder = riblt.Decoder(local.width)
+while len(decoder) == 0 or not decoder.is_empty():
+    start = len(decoder)
+    for index, cs in enumerate(remote.coded_symbols(start, start + 5)):
+        decoder.add(local.collapse_coded_symbol(start + index, cs))
+local_only, remote_only = decoder.peel()
+assert sorted(local_only) == local_ids[:3], local_only
+assert sorted(remote_only) == [i.to_bytes(8, "little") for i in (100, 101)]
+assert decoder.peel() == (local_only, remote_only)
+"#);
+    }
+
+    #[test]
+    fn test_serialisation() {
+        run(r#"
+encoder = riblt.Encoder([b"abcd", b"efgh"])
+cs = encoder.coded_symbols(0, 1)[0]
+assert len(cs) == 4 + 16
+sum, hash, count = riblt.decode_coded_symbol(cs, 4)
+assert count == 2
+assert sum == bytes(a ^ b for a, b in zip(b"abcd", b"efgh"))
+assert riblt.encode_coded_symbol(sum, hash, count) == cs
+"#);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        run(r#"
+def raises(f):
+    try:
+        f()
+    except ValueError as err:
+        return str(err)
+    raise AssertionError("no ValueError")
+
+assert "item 1 is 3 bytes" in raises(lambda: riblt.Encoder([b"abcd", b"abc"]))
+assert "width" in raises(lambda: riblt.Encoder([]))
+assert riblt.Encoder([], width=4).fingerprint() == (0, 0)
+raises(lambda: riblt.Encoder([b"abc"]))
+raises(lambda: riblt.Decoder(7))
+raises(lambda: riblt.Decoder(4).add(b"short"))
+raises(lambda: riblt.decode_coded_symbol(b"short", 4))
+"#);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::encoder::{RatelessIBLT, UnmanagedRatelessIBLT};
+use crate::error::Error;
+use crate::source::SymbolSource;
+use crate::symbol;
+use alloc::vec::Vec;
+
+/// The shard a symbol belongs to, taken from the top shard_bits bits of Symbol::hash_.
+///
+/// Use this to partition the set when it is stored, so each shard can be scanned on its own,
+/// see ShardedRatelessIBLT::from_shards.
+pub fn shard_of<T: symbol::Symbol>(symbol: &T, shard_bits: u32) -> usize {
+    assert!(shard_bits < 32, "shard_bits must be less than 32");
+    if shard_bits == 0 {
+        return 0;
+    }
+    (symbol.hash_() >> (64 - shard_bits)) as usize
+}
+
+/// A set split into 2^shard_bits shards by hash, with an independent RatelessIBLT per shard.
+///
+/// Build it with from_shards, from a set that is partitioned by shard_of as it is stored, so
+/// each block of a shard only scans that shard. new partitions a set that isn't, in memory.
+///
+/// Both peers must use the same shard_bits. Then:
+/// - The fingerprints of each shard can be compared first, and only the shards that differ need
+///   any coded symbols streaming (see differing_shards).
+/// - Each shard holds a fraction of the set and of the difference, so shards can be encoded,
+///   streamed and peeled independently, e.g. one thread per shard (see shards_mut).
+/// - The differences peeled from every shard together are the difference between the sets
+///   (see peel_shards).
+pub struct ShardedRatelessIBLT<T, S>
+where
+    T: symbol::Symbol,
+    S: SymbolSource<Symbol = T>,
+{
+    shard_bits: u32,
+    shards: Vec<RatelessIBLT<T, S>>,
+}
+
+impl<T> ShardedRatelessIBLT<T, Vec<T>>
+where
+    T: symbol::Symbol,
+{
+    /// Shard a set that isn't already partitioned.
+    ///
+    /// The set is scanned once and each shard's symbols are copied into memory, so later blocks
+    /// only scan their own shard. For a set larger than RAM, partition it as it is stored and use
+    /// from_shards instead.
+    pub fn new<S>(source: S, shard_bits: u32) -> Result<Self, Error>
+    where
+        S: SymbolSource<Symbol = T>,
+    {
+        assert!(shard_bits < 32, "shard_bits must be less than 32");
+        let mut sources: Vec<Vec<T>> = (0..1usize << shard_bits).map(|_| Vec::new()).collect();
+        for item in source.scan().map_err(Error::from_source)? {
+            let symbol = item.map_err(Error::from_source)?;
+            sources[shard_of(&symbol, shard_bits)].push(symbol);
+        }
+        Ok(Self::from_shards(shard_bits, sources))
+    }
+}
+
+impl<T, S> ShardedRatelessIBLT<T, S>
+where
+    T: symbol::Symbol,
+    S: SymbolSource<Symbol = T>,
+{
+    /// Construct from a set that is already partitioned, one source per shard.
+    ///
+    /// This is the way to shard a large set: each shard's source is only scanned for that
+    /// shard's coded symbols.
+    ///
+    /// `sources[i]` must hold exactly the symbols for which `shard_of(symbol, shard_bits) == i`.
+    pub fn from_shards(shard_bits: u32, sources: Vec<S>) -> Self {
+        assert!(shard_bits < 32, "shard_bits must be less than 32");
+        assert_eq!(
+            sources.len(),
+            1 << shard_bits,
+            "there must be 2^shard_bits sources"
+        );
+        ShardedRatelessIBLT {
+            shard_bits,
+            shards: sources.into_iter().map(RatelessIBLT::new).collect(),
+        }
+    }
+
+    pub fn shard_bits(&self) -> u32 {
+        self.shard_bits
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The RatelessIBLT for a single shard, stream its coded symbols to the remote
+    ///
+    /// Returns Error::UnknownShard if there is no such shard, e.g. a remote asked for one.
+    pub fn shard(&mut self, shard: usize) -> Result<&mut RatelessIBLT<T, S>, Error> {
+        self.shards
+            .get_mut(shard)
+            .ok_or(Error::UnknownShard { shard })
+    }
+
+    /// Every shard, to hand out to threads
+    pub fn shards_mut(&mut self) -> &mut [RatelessIBLT<T, S>] {
+        &mut self.shards
+    }
+
+    /// Returns the coded symbol at the provided index, of the provided shard.
+    ///
+    /// Returns Error::UnknownShard if there is no such shard.
+    pub fn get_coded_symbol(
+        &mut self,
+        shard: usize,
+        index: usize,
+    ) -> Result<symbol::CodedSymbol<T>, Error> {
+        self.shard(shard)?.get_coded_symbol(index)
+    }
+
+    /// The fingerprint of every shard, send these to the remote first.
+    ///
+    /// Shards that haven't generated any coded symbols yet scan their set.
+    pub fn fingerprints(&self) -> Result<Vec<symbol::Fingerprint>, Error> {
+        self.shards.iter().map(RatelessIBLT::fingerprint).collect()
+    }
+
+    /// The shards whose fingerprints differ from the remote's, only these need reconciling.
+    ///
+    /// Returns Error::LengthMismatch if the remote has a different number of shards.
+    pub fn differing_shards(&self, remote: &[symbol::Fingerprint]) -> Result<Vec<usize>, Error> {
+        if remote.len() != self.shards.len() {
+            return Err(Error::LengthMismatch {
+                left: self.shards.len(),
+                right: remote.len(),
+            });
+        }
+        Ok(self
+            .fingerprints()?
+            .iter()
+            .zip(remote)
+            .enumerate()
+            .filter(|(_, (local, remote))| local != remote)
+            .map(|(shard, _)| shard)
+            .collect())
+    }
+
+    /// Collapse the remote coded symbols of each listed shard against ours, and peel them.
+    ///
+    /// Returns every difference peeled, merged into one list, and the shards that couldn't be
+    /// fully decoded. Those shards need more coded symbols from the remote. A shard listed with
+    /// no coded symbols at all is incomplete too, it was listed because it differs.
+    ///
+    /// Returns Error::UnknownShard if a listed shard doesn't exist.
+    pub fn peel_shards<'a, I>(
+        &mut self,
+        remote: I,
+    ) -> Result<(Vec<symbol::Difference<T>>, Vec<usize>), Error>
+    where
+        I: IntoIterator<Item = (usize, &'a UnmanagedRatelessIBLT<T>)>,
+        T: 'a,
+    {
+        let mut differences = Vec::new();
+        let mut incomplete = Vec::new();
+        for (shard, coded_symbols) in remote {
+            let riblt = self.shard(shard)?;
+            if coded_symbols.coded_symbols.is_empty() {
+                incomplete.push(shard);
+                continue;
+            }
+            let mut collapsed = riblt.collapse(coded_symbols)?;
+            differences.extend(collapsed.peel_iter());
+            if !collapsed.is_empty() {
+                incomplete.push(shard);
+            }
+        }
+        Ok((differences, incomplete))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::SimpleSymbol;
+    use std::collections::HashSet;
+
+    fn values(symbols: &HashSet<SimpleSymbol>) -> HashSet<u64> {
+        symbols.iter().map(|s| s.value).collect()
+    }
+
+    #[test]
+    fn test_shard_of() {
+        let symbols: Vec<SimpleSymbol> = (0..4000).map(|value| SimpleSymbol { value }).collect();
+        let mut counts = [0; 8];
+        for symbol in &symbols {
+            assert_eq!(shard_of(symbol, 0), 0);
+            counts[shard_of(symbol, 3)] += 1;
+        }
+        // Roughly even
+        assert!(counts.iter().all(|&count| (350..650).contains(&count)));
+    }
+
+    #[test]
+    fn test_sharded_reconciliation() {
+        let items_local: HashSet<SimpleSymbol> =
+            (0..2000).map(|value| SimpleSymbol { value }).collect();
+        let mut items_remote = items_local.clone();
+        // Only a handful of differences, so most shards match
+        for value in [3, 500, 1999] {
+            items_remote.remove(&SimpleSymbol { value });
+        }
+        items_remote.insert(SimpleSymbol { value: 5000 });
+
+        let mut local = ShardedRatelessIBLT::new(items_local, 4).unwrap();
+        let mut remote = ShardedRatelessIBLT::new(items_remote, 4).unwrap();
+        assert_eq!(local.num_shards(), 16);
+
+        let differing = local
+            .differing_shards(&remote.fingerprints().unwrap())
+            .unwrap();
+        assert!(!differing.is_empty() && differing.len() <= 4);
+
+        let mut streamed: Vec<(usize, UnmanagedRatelessIBLT<SimpleSymbol>)> = Vec::new();
+        for &shard in &differing {
+            let mut coded_symbols = UnmanagedRatelessIBLT::new();
+            for index in 0..10 {
+                coded_symbols.add_coded_symbol(&remote.get_coded_symbol(shard, index).unwrap());
+            }
+            streamed.push((shard, coded_symbols));
+        }
+
+        let (differences, incomplete) = local
+            .peel_shards(streamed.iter().map(|(shard, cs)| (*shard, cs)))
+            .unwrap();
+        assert!(incomplete.is_empty());
+        let (local_only, remote_only) = symbol::split_differences_into_sets(differences);
+        assert_eq!(values(&local_only), HashSet::from([3, 500, 1999]));
+        assert_eq!(values(&remote_only), HashSet::from([5000]));
+    }
+
+    #[test]
+    fn test_parallel_shards() {
+        let items: Vec<SimpleSymbol> = (0..1000).map(|value| SimpleSymbol { value }).collect();
+        let sources: Vec<Vec<SimpleSymbol>> = (0..4)
+            .map(|shard| {
+                items
+                    .iter()
+                    .filter(|s| shard_of(*s, 2) == shard)
+                    .cloned()
+                    .collect()
+            })
+            .collect();
+        let mut partitioned = ShardedRatelessIBLT::from_shards(2, sources);
+        let filtered = ShardedRatelessIBLT::new(items, 2).unwrap();
+
+        std::thread::scope(|scope| {
+            for shard in partitioned.shards_mut() {
+                scope.spawn(move || shard.extend_coded_symbols(0).unwrap());
+            }
+        });
+
+        let fingerprints = partitioned.fingerprints().unwrap();
+        assert_eq!(fingerprints.iter().map(|f| f.count).sum::<i64>(), 1000);
+        assert!(filtered.differing_shards(&fingerprints).unwrap().is_empty());
+        assert!(matches!(
+            filtered.differing_shards(&fingerprints[..3]),
+            Err(Error::LengthMismatch { left: 4, right: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_shard() {
+        let items: Vec<SimpleSymbol> = (0..100).map(|value| SimpleSymbol { value }).collect();
+        let mut sharded = ShardedRatelessIBLT::new(items, 2).unwrap();
+        assert!(sharded.get_coded_symbol(3, 0).is_ok());
+        assert!(matches!(
+            sharded.get_coded_symbol(4, 0),
+            Err(Error::UnknownShard { shard: 4 })
+        ));
+        assert!(matches!(
+            sharded.shard(usize::MAX),
+            Err(Error::UnknownShard { shard: usize::MAX })
+        ));
+        let remote = UnmanagedRatelessIBLT::new();
+        assert!(matches!(
+            sharded.peel_shards([(4, &remote)]),
+            Err(Error::UnknownShard { shard: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_peel_shards_without_coded_symbols() {
+        let local: Vec<SimpleSymbol> = (0..100).map(|value| SimpleSymbol { value }).collect();
+        let remote: Vec<SimpleSymbol> = (1..100).map(|value| SimpleSymbol { value }).collect();
+        let mut local = ShardedRatelessIBLT::new(local, 2).unwrap();
+        let remote = ShardedRatelessIBLT::new(remote, 2).unwrap();
+        let differing = local
+            .differing_shards(&remote.fingerprints().unwrap())
+            .unwrap();
+        assert_eq!(differing.len(), 1);
+
+        // Nothing streamed yet, the shard still differs
+        let empty = UnmanagedRatelessIBLT::new();
+        let (differences, incomplete) = local.peel_shards([(differing[0], &empty)]).unwrap();
+        assert!(differences.is_empty());
+        assert_eq!(incomplete, differing);
+    }
+}
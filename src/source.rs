@@ -0,0 +1,313 @@
+use crate::symbol;
+use core::convert::Infallible;
+#[cfg(feature = "std")]
+use alloc::vec;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::fs::{File, OpenOptions};
+#[cfg(feature = "std")]
+use std::io::{self, BufReader, BufWriter, Read, Write};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+/// A SymbolSource is the set a RatelessIBLT is built from.
+///
+/// The RatelessIBLT generates its coded symbols a block at a time, scanning the whole set for
+/// each block, so scan may be called many times. Each scan must produce the same symbols
+/// (in any order) until the set changes. It is the responsibility of the calling code to create
+/// a new RatelessIBLT when the set changes.
+///
+/// Scanning can fail, e.g. when the set is read from disk or a database. Errors are returned from
+/// RatelessIBLT::extend_coded_symbols (and the methods that call it), wrapped in Error::Source.
+///
+/// Anything that can be cloned and iterated over (a HashSet, a Vec, a range mapped to symbols...)
+/// is already a SymbolSource that never fails.
+pub trait SymbolSource {
+    type Symbol: symbol::Symbol;
+    type Error: core::error::Error + Send + Sync + 'static;
+
+    /// Start a new scan over every symbol in the set
+    fn scan(&self) -> Result<impl Iterator<Item = Result<Self::Symbol, Self::Error>>, Self::Error>;
+}
+
+impl<I> SymbolSource for I
+where
+    I: IntoIterator + Clone,
+    I::Item: symbol::Symbol,
+{
+    type Symbol = I::Item;
+    type Error = Infallible;
+
+    fn scan(&self) -> Result<impl Iterator<Item = Result<Self::Symbol, Self::Error>>, Self::Error> {
+        Ok(self.clone().into_iter().map(Ok))
+    }
+}
+
+/// Wraps a SymbolSource, only yielding the symbols inserted at or before the cutoff.
+///
+/// See RatelessIBLT::with_window.
+#[derive(Clone, Debug)]
+pub struct Windowed<S> {
+    source: S,
+    cutoff: u64,
+}
+
+impl<S> Windowed<S> {
+    pub fn new(source: S, cutoff: u64) -> Self {
+        Windowed { source, cutoff }
+    }
+
+    pub fn cutoff(&self) -> u64 {
+        self.cutoff
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.source
+    }
+}
+
+impl<S> SymbolSource for Windowed<S>
+where
+    S: SymbolSource,
+    S::Symbol: symbol::TimestampedSymbol,
+{
+    type Symbol = S::Symbol;
+    type Error = S::Error;
+
+    fn scan(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Self::Symbol, Self::Error>>, Self::Error> {
+        let cutoff = self.cutoff;
+        Ok(self.source.scan()?.filter(move |item| match item {
+            Ok(symbol) => symbol::TimestampedSymbol::inserted_at(symbol) <= cutoff,
+            // always pass errors through
+            Err(_) => true,
+        }))
+    }
+}
+
+/// A set stored in a flat file of fixed-width records, one record per symbol.
+///
+/// Each record is T::BYTE_ARRAY_LENGTH bytes, as produced by encode_to_bytes, with no header or
+/// separators. The file is re-read (buffered) for every scan, so sets much larger than RAM can be
+/// encoded. Use append to add symbols in the same layout.
+///
+/// The file must not contain duplicate records, as with any other set.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct FileSymbolSource<T>
+where
+    T: symbol::Symbol,
+{
+    path: PathBuf,
+    _marker: PhantomData<T>,
+}
+
+#[cfg(feature = "std")]
+impl<T> FileSymbolSource<T>
+where
+    T: symbol::Symbol,
+{
+    /// The file doesn't need to exist until the first scan or append
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileSymbolSource {
+            path: path.as_ref().to_path_buf(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append symbols to the end of the file, creating it if needed.
+    ///
+    /// Remember that coded symbols generated before the append no longer match the set.
+    pub fn append<'a, I>(&self, symbols: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a,
+    {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut writer = BufWriter::new(file);
+        for symbol in symbols {
+            let encoded = symbol.encode_to_bytes();
+            if encoded.len() != T::BYTE_ARRAY_LENGTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "encode_to_bytes did not return BYTE_ARRAY_LENGTH bytes",
+                ));
+            }
+            writer.write_all(&encoded)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> SymbolSource for FileSymbolSource<T>
+where
+    T: symbol::Symbol,
+{
+    type Symbol = T;
+    type Error = io::Error;
+
+    fn scan(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Self::Symbol, Self::Error>>, Self::Error> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let mut done = false;
+
+        Ok(std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            match read_record(&mut reader, T::BYTE_ARRAY_LENGTH) {
+                Ok(Some(record)) => Some(Ok(T::decode_from_bytes(&record))),
+                Ok(None) => {
+                    done = true;
+                    None
+                }
+                Err(err) => {
+                    done = true;
+                    Some(Err(err))
+                }
+            }
+        }))
+    }
+}
+
+// Read exactly one record, or None at a clean end of file.
+// A truncated record at the end of the file is an error rather than being silently dropped.
+#[cfg(feature = "std")]
+fn read_record<R: Read>(reader: &mut R, len: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut record = vec![0u8; len];
+    let mut filled = 0;
+    while filled < len {
+        match reader.read(&mut record[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file ends part way through a record",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Some(record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::RatelessIBLT;
+    use crate::error::Error;
+    use crate::test_helpers::SimpleSymbol;
+    use std::cell::Cell;
+    use std::io;
+
+    // Produces the values 0..10, but fails part way through the scan once 'fail' is set
+    struct FlakySource {
+        fail: Cell<bool>,
+    }
+
+    impl SymbolSource for FlakySource {
+        type Symbol = SimpleSymbol;
+        type Error = io::Error;
+
+        fn scan(
+            &self,
+        ) -> Result<impl Iterator<Item = Result<Self::Symbol, Self::Error>>, Self::Error> {
+            let fail = self.fail.get();
+            Ok((0..10).map(move |value| {
+                if fail && value == 5 {
+                    Err(io::Error::other("disk on fire"))
+                } else {
+                    Ok(SimpleSymbol { value })
+                }
+            }))
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_source() {
+        let path = std::env::temp_dir().join(format!("riblt-source-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let source = FileSymbolSource::new(&path);
+
+        // Missing file
+        assert!(source.scan().is_err());
+
+        let symbols: Vec<SimpleSymbol> = (0..100).map(|value| SimpleSymbol { value }).collect();
+        source.append(&symbols[..60]).unwrap();
+        source.append(&symbols[60..]).unwrap();
+        let scanned: Vec<SimpleSymbol> = source.scan().unwrap().map(|s| s.unwrap()).collect();
+        assert_eq!(scanned, symbols);
+
+        // Encoding from the file matches encoding from memory
+        let mut from_file = RatelessIBLT::new(source.clone());
+        let mut from_memory = RatelessIBLT::new(symbols.clone());
+        for index in [0, 1, 50, 200] {
+            let coded_symbol = from_file.get_coded_symbol(index).unwrap();
+            let expected = from_memory.get_coded_symbol(index).unwrap();
+            assert_eq!(coded_symbol.sum, expected.sum);
+            assert_eq!(coded_symbol.hash, expected.hash);
+            assert_eq!(coded_symbol.count, expected.count);
+        }
+
+        // A truncated record at the end of the file is reported
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[1, 2, 3])
+            .unwrap();
+        let results: Vec<_> = source.scan().unwrap().collect();
+        assert_eq!(results.len(), 101);
+        assert!(results[100].is_err());
+        assert!(matches!(
+            RatelessIBLT::new(source).extend_coded_symbols(0),
+            Err(Error::Source(_))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_source_errors() {
+        let mut iblt = RatelessIBLT::new(FlakySource {
+            fail: Cell::new(false),
+        });
+        let mut expected = RatelessIBLT::new((0..10).map(|value| SimpleSymbol { value }));
+        let first_block = iblt.get_coded_symbol(0).unwrap();
+        assert_eq!(first_block.count, 10);
+
+        iblt.source.fail.set(true);
+        let len = iblt.coded_symbols.len();
+        match iblt.get_coded_symbol(len) {
+            Err(Error::Source(err)) => assert_eq!(err.to_string(), "disk on fire"),
+            other => panic!("Not expecting {:?}", other.map(|_| ())),
+        }
+        // The partially generated block was thrown away
+        assert_eq!(iblt.coded_symbols.len(), len);
+
+        // And is generated properly once the source recovers
+        iblt.source.fail.set(false);
+        for index in [len, len + 10, 3 * len] {
+            let coded_symbol = iblt.get_coded_symbol(index).unwrap();
+            let expected_coded_symbol = expected.get_coded_symbol(index).unwrap();
+            assert_eq!(coded_symbol.sum, expected_coded_symbol.sum);
+            assert_eq!(coded_symbol.count, expected_coded_symbol.count);
+        }
+    }
+}
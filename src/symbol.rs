@@ -1,29 +1,92 @@
+use alloc::vec;
+use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
-use std::hash::{DefaultHasher, Hash, Hasher};
-use std::marker::PhantomData;
+use core::fmt::Debug;
+#[cfg(any(feature = "std", test))]
+use std::collections::HashSet;
+use siphasher::sip::SipHasher13;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
 // use std::simd::{u8x16, Simd};
 
 /// A symbol is an item in the set
 pub trait Symbol: Clone + Debug {
     const BYTE_ARRAY_LENGTH: usize;
 
+    /// Identifies how hash_() hashes symbols, it is saved alongside persisted coded symbols and
+    /// sent in the Header.
+    ///
+    /// If you override hash_(), pick a value of your own and change it whenever the hashing
+    /// changes, so that coded symbols produced with the old hashing are not loaded by mistake.
+    ///
+    /// 0 was std's DefaultHasher over the encoded Vec, 1 is SipHash-1-3 over the encoded bytes.
+    const HASH_SCHEME: u32 = 1;
+
     /// The Symbol trait only requires that the type can be encoded to a fixed number of bytes.
     /// You just need to know the size of the byte array that will be produced and then set BYTE_ARRAY_LENGTH to match.
     /// I recommend using a serialization library like bincode.
     fn encode_to_bytes(&self) -> Vec<u8>;
+    #[allow(clippy::ptr_arg)]
     fn decode_from_bytes(bytes: &Vec<u8>) -> Self;
 
     /// hash_() calculates the hash of the symbol.
     /// This implementation can be overridden if needed.
+    ///
+    /// Both sides must hash symbols the same way, so we use SipHash-1-3 with fixed keys over the
+    /// encoded bytes. std's DefaultHasher makes no promise to be stable across Rust versions,
+    /// and hashing the Vec would include its length as a usize, which differs between 32 bit
+    /// (e.g. wasm32) and 64 bit peers.
     fn hash_(&self) -> u64 {
-        let encoded = self.encode_to_bytes();
-        let mut hasher = DefaultHasher::new();
-        encoded.hash(&mut hasher);
-        hasher.finish()
+        sip_hash(&self.encode_to_bytes())
+    }
+
+    /// The hash_() of the symbol encoded as bytes, without decoding it, or None if that isn't
+    /// known. Peeling uses this to rule out coded symbols before allocating anything for them.
+    ///
+    /// The default only knows the built in hashing (HASH_SCHEME 1). If you override hash_() and
+    /// change HASH_SCHEME as described above, this returns None, or you can override it too.
+    fn hash_encoded(bytes: &[u8]) -> Option<u64> {
+        (Self::HASH_SCHEME == 1).then(|| sip_hash(bytes))
+    }
+}
+
+// SipHash-1-3 with fixed keys, the hashing of HASH_SCHEME 1
+fn sip_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = SipHasher13::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// A Symbol that is just N bytes, e.g. a fixed size id or a digest.
+///
+/// Handy when the set is already made of fixed width byte strings, and used by the language
+/// bindings where the width is chosen at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ByteSymbol<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Symbol for ByteSymbol<N> {
+    const BYTE_ARRAY_LENGTH: usize = N;
+
+    fn encode_to_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    fn decode_from_bytes(bytes: &Vec<u8>) -> Self {
+        let mut symbol = [0u8; N];
+        symbol.copy_from_slice(bytes);
+        ByteSymbol(symbol)
     }
 }
 
+/// A symbol that knows when it was inserted into the set.
+///
+/// This lets both sides of a reconciliation ignore recently inserted symbols that are still being
+/// gossiped, see RatelessIBLT::with_window.
+/// The unit is up to you (e.g. seconds since the unix epoch), as long as every peer uses the same one.
+pub trait TimestampedSymbol: Symbol {
+    fn inserted_at(&self) -> u64;
+}
+
 /// A RIBLT is an infinite sequence of CodedSymbols
 ///
 /// The 'sum' field is the XOR of the symbols encoded into this CodedSymbol
@@ -44,16 +107,91 @@ pub struct CodedSymbol<T: Symbol> {
     pub count: i64,
 }
 
-/// If a symbol can be successfully 'peeled' out of a CodedSymbol, it is returned in the PeelableResult.
+/// A summary of a whole set: the XOR of the hashes of every symbol, and the number of symbols.
+///
+/// Every symbol is mapped to index 0, so this is also the hash and count of the CodedSymbol at
+/// index 0. If two sets have the same fingerprint they are almost certainly the same set, and
+/// there is no need to stream any coded symbols.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub hash: u64,
+    pub count: i64,
+}
+
+impl Fingerprint {
+    /// Add a symbol to the fingerprint
+    pub fn add<T: Symbol>(&mut self, symbol: &T) {
+        self.hash ^= symbol.hash_();
+        self.count += 1;
+    }
+}
+
+impl<T: Symbol> From<&CodedSymbol<T>> for Fingerprint {
+    fn from(coded_symbol: &CodedSymbol<T>) -> Self {
+        Fingerprint {
+            hash: coded_symbol.hash,
+            count: coded_symbol.count,
+        }
+    }
+}
+
+/// A symbol that has been 'peeled' out of a CodedSymbol, and which side it came from.
 ///
-/// This enum acts as a wrapper to keep track of if the symbol was local or remote.
+/// Local symbols are in the local set but not the remote set, Remote symbols are in the remote
+/// set but not the local set.
 ///
-/// It is very common that the symbol is not peelable, so this enum also has the NotPeelable variant.
+/// Peeling functions return an `Option<Difference<T>>`, None when nothing could be peeled.
 #[derive(PartialEq, Eq, Clone, Debug)]
-pub enum PeelableResult<T: Symbol> {
+pub enum Difference<T: Symbol> {
     Local(T),
     Remote(T),
-    NotPeelable,
+}
+
+impl<T: Symbol> Difference<T> {
+    /// The symbol, regardless of which side it came from
+    pub fn symbol(&self) -> &T {
+        match self {
+            Difference::Local(symbol) | Difference::Remote(symbol) => symbol,
+        }
+    }
+
+    /// Same as symbol, but takes ownership rather than borrowing
+    pub fn into_symbol(self) -> T {
+        match self {
+            Difference::Local(symbol) | Difference::Remote(symbol) => symbol,
+        }
+    }
+}
+
+/// Split a list of differences into (local_only, remote_only)
+pub fn split_differences<T: Symbol>(
+    differences: impl IntoIterator<Item = Difference<T>>,
+) -> (Vec<T>, Vec<T>) {
+    let mut local_only = Vec::new();
+    let mut remote_only = Vec::new();
+    for difference in differences {
+        match difference {
+            Difference::Local(symbol) => local_only.push(symbol),
+            Difference::Remote(symbol) => remote_only.push(symbol),
+        }
+    }
+    (local_only, remote_only)
+}
+
+/// Same as split_differences, but collects into HashSets
+#[cfg(any(feature = "std", test))]
+pub fn split_differences_into_sets<T: Symbol + Eq + Hash>(
+    differences: impl IntoIterator<Item = Difference<T>>,
+) -> (HashSet<T>, HashSet<T>) {
+    let mut local_only = HashSet::new();
+    let mut remote_only = HashSet::new();
+    for difference in differences {
+        match difference {
+            Difference::Local(symbol) => local_only.insert(symbol),
+            Difference::Remote(symbol) => remote_only.insert(symbol),
+        };
+    }
+    (local_only, remote_only)
 }
 
 #[derive(Clone)]
@@ -62,6 +200,12 @@ pub enum Direction {
     Remove,
 }
 
+impl<T: Symbol> Default for CodedSymbol<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Symbol> CodedSymbol<T> {
     pub fn new() -> Self {
         let sum = vec![0u8; T::BYTE_ARRAY_LENGTH];
@@ -110,9 +254,10 @@ impl<T: Symbol> CodedSymbol<T> {
         //     .collect();
 
         self.hash ^= s.hash_();
+        // counts can come from the network, so never panic on overflow
         match direction {
-            Direction::Add => self.count += 1,
-            Direction::Remove => self.count -= 1,
+            Direction::Add => self.count = self.count.wrapping_add(1),
+            Direction::Remove => self.count = self.count.wrapping_sub(1),
         };
     }
 
@@ -133,7 +278,7 @@ impl<T: Symbol> CodedSymbol<T> {
         let mut new_coded_symbol = self.clone();
 
         new_coded_symbol.hash ^= b.hash;
-        new_coded_symbol.count += b.count;
+        new_coded_symbol.count = new_coded_symbol.count.wrapping_add(b.count);
 
         new_coded_symbol.sum = self
             .sum
@@ -162,7 +307,7 @@ impl<T: Symbol> CodedSymbol<T> {
 
         // new_coded_symbol.symbol = new_coded_symbol.symbol.xor(&b.symbol);
         new_coded_symbol.hash ^= b.hash;
-        new_coded_symbol.count -= b.count;
+        new_coded_symbol.count = new_coded_symbol.count.wrapping_sub(b.count);
 
         new_coded_symbol.sum = self
             .sum
@@ -180,42 +325,28 @@ impl<T: Symbol> CodedSymbol<T> {
     /// symbol. It could be the xor of two local and one remote symbols. This is why we also
     /// check the hash.
     pub fn is_peelable(&self) -> bool {
-        if self.count == 1 || self.count == -1 {
-            if self.hash == T::decode_from_bytes(&self.sum).hash_() {
-                return true;
-            }
-        }
-        return false;
+        (self.count == 1 || self.count == -1) && self.hash == T::decode_from_bytes(&self.sum).hash_()
     }
 
-    /// Peel extracts a symbol from the CodedSymbol (if possible) and returns it in a PeelableResult
-    /// A PeelableResult is used to keep track of if the symbol was local or remote (or was not
-    /// able to be peeled).
-    pub fn peel(&mut self) -> PeelableResult<T> {
-        if self.is_peelable() {
-            let return_result = if self.count == 1 {
-                PeelableResult::Local(T::decode_from_bytes(&self.sum))
-            } else {
-                PeelableResult::Remote(T::decode_from_bytes(&self.sum))
-            };
-
-            *self = CodedSymbol::new();
-            return return_result;
-        }
-        PeelableResult::NotPeelable
+    /// Peel extracts a symbol from the CodedSymbol (if possible) and returns it in a Difference
+    /// A Difference is used to keep track of if the symbol was local or remote.
+    /// None is returned if the CodedSymbol was not able to be peeled.
+    pub fn peel(&mut self) -> Option<Difference<T>> {
+        let difference = self.peel_peek()?;
+        *self = CodedSymbol::new();
+        Some(difference)
     }
     /// same as peel, but does not modify the CodedSymbol
-    pub fn peel_peek(&self) -> PeelableResult<T> {
-        if self.is_peelable() {
-            let return_result = if self.count == 1 {
-                PeelableResult::Local(T::decode_from_bytes(&self.sum))
-            } else {
-                PeelableResult::Remote(T::decode_from_bytes(&self.sum))
-            };
-
-            return return_result;
+    pub fn peel_peek(&self) -> Option<Difference<T>> {
+        if !self.is_peelable() {
+            return None;
+        }
+        let symbol = T::decode_from_bytes(&self.sum);
+        if self.count == 1 {
+            Some(Difference::Local(symbol))
+        } else {
+            Some(Difference::Remote(symbol))
         }
-        PeelableResult::NotPeelable
     }
 
     /// Checks if the CodedSymbol contains no symbols
@@ -238,6 +369,9 @@ mod tests {
     use super::*;
     use crate::test_helpers::SimpleSymbol;
 
+    // SipHash-1-3 with zero keys of eight zero bytes
+    const HASH_OF_ZERO: u64 = 0xbd60acb658c79e45;
+
     #[test]
     fn test_symbol() {
         let symbol1 = SimpleSymbol { value: 42 };
@@ -246,37 +380,68 @@ mod tests {
         let mut coded_symbol = CodedSymbol::new();
 
         println!("0 is peelable {}", coded_symbol.is_peelable());
-        assert_eq!(coded_symbol.is_peelable(), false);
+        assert!(!coded_symbol.is_peelable());
 
         coded_symbol.apply(&symbol1, Direction::Add);
         println!("1 is peelable {}", coded_symbol.is_peelable());
-        assert_eq!(coded_symbol.is_peelable(), true);
+        assert!(coded_symbol.is_peelable());
 
         coded_symbol.apply(&symbol2, Direction::Add);
         println!("2 is peelable {}", coded_symbol.is_peelable());
-        assert_eq!(coded_symbol.is_peelable(), false);
+        assert!(!coded_symbol.is_peelable());
 
         coded_symbol.apply(&symbol1, Direction::Remove);
         println!("3 is peelable {}", coded_symbol.is_peelable());
-        assert_eq!(coded_symbol.is_peelable(), true);
+        assert!(coded_symbol.is_peelable());
 
         println!("CodedSymbol: {:?}", coded_symbol);
 
         let peeled_symbol = coded_symbol.peel();
         match peeled_symbol {
-            PeelableResult::Local(symbol) => {
+            Some(Difference::Local(symbol)) => {
                 println!("Peeled Local Symbol: {:?}", symbol);
                 assert_eq!(symbol.value, symbol2.value);
             }
-            PeelableResult::Remote(symbol) => {
+            Some(Difference::Remote(symbol)) => {
                 println!("Peeled Remote Symbol: {:?}", symbol);
                 assert_eq!(symbol.value, symbol2.value);
             }
-            PeelableResult::NotPeelable => {
+            None => {
                 println!("No symbol to peel");
-                assert!(false);
+                panic!("Expected a symbol to be peeled");
             }
         }
+        assert!(coded_symbol.is_empty());
+        assert!(coded_symbol.peel().is_none());
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        // Peers on other platforms (and other versions of this crate) rely on these values
+        assert_eq!(SimpleSymbol { value: 0 }.hash_(), HASH_OF_ZERO);
+        assert_eq!(ByteSymbol([0u8; 8]).hash_(), HASH_OF_ZERO);
+        assert_ne!(ByteSymbol([0u8; 7]).hash_(), HASH_OF_ZERO);
+        assert_eq!(SimpleSymbol::hash_encoded(&[0u8; 8]), Some(HASH_OF_ZERO));
+    }
+
+    #[test]
+    fn test_split_differences() {
+        let differences = vec![
+            Difference::Local(SimpleSymbol { value: 1 }),
+            Difference::Remote(SimpleSymbol { value: 2 }),
+            Difference::Local(SimpleSymbol { value: 3 }),
+        ];
+
+        let (local_only, remote_only) = split_differences(differences.clone());
+        assert_eq!(local_only, vec![SimpleSymbol { value: 1 }, SimpleSymbol { value: 3 }]);
+        assert_eq!(remote_only, vec![SimpleSymbol { value: 2 }]);
+
+        let (local_only, remote_only) = split_differences_into_sets(differences);
+        assert_eq!(
+            local_only,
+            HashSet::from([SimpleSymbol { value: 1 }, SimpleSymbol { value: 3 }])
+        );
+        assert_eq!(remote_only, HashSet::from([SimpleSymbol { value: 2 }]));
     }
 }
 
@@ -0,0 +1,608 @@
+use crate::symbol;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+/// A growable sequence of CodedSymbols, stored as columns rather than one struct per CodedSymbol.
+///
+/// The 'sum' fields are stored back to back in one contiguous buffer, with the 'hash' and 'count'
+/// fields in two more contiguous arrays. This avoids a separate heap allocation for each
+/// CodedSymbol, which matters once we have millions of them.
+///
+/// By default the columns are held in memory. With the 'mmap' feature enabled they can instead be
+/// backed by memory mapped files (see with_mmap_dir), allowing tables larger than RAM.
+///
+/// CodedSymbols are copied in and out of the table, see get and push.
+pub struct CodedSymbolTable<T>
+where
+    T: symbol::Symbol,
+{
+    sums: Column<u8>,
+    hashes: Column<u64>,
+    counts: Column<i64>,
+    // where peeling found a symbol it had already peeled, see encoder::decode_status
+    peeled_again_at: Option<usize>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for CodedSymbolTable<T>
+where
+    T: symbol::Symbol,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> CodedSymbolTable<T>
+where
+    T: symbol::Symbol,
+{
+    /// An empty table held in memory
+    pub fn new() -> Self {
+        CodedSymbolTable {
+            sums: Column::memory(),
+            hashes: Column::memory(),
+            counts: Column::memory(),
+            peeled_again_at: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// An empty table backed by memory mapped files in the provided directory.
+    ///
+    /// The files 'sums', 'hashes' and 'counts' are created (or truncated) in the directory.
+    /// They are scratch space in the native byte order, not a format to share between machines,
+    /// and are left in place when the table is dropped.
+    ///
+    /// Growing the files can fail (e.g. when the disk is full), which panics in the same way
+    /// running out of memory would.
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap_dir<P: AsRef<std::path::Path>>(dir: P) -> std::io::Result<Self> {
+        let dir = dir.as_ref();
+        Ok(CodedSymbolTable {
+            sums: Column::mapped(&dir.join("sums"))?,
+            hashes: Column::mapped(&dir.join("hashes"))?,
+            counts: Column::mapped(&dir.join("counts"))?,
+            peeled_again_at: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// returns true if the columns are backed by memory mapped files, see with_mmap_dir
+    #[cfg(feature = "mmap")]
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.hashes, Column::Mapped(_))
+    }
+
+    /// The number of CodedSymbols in the table
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// returns true if there are no CodedSymbols in the table
+    ///
+    /// Note this is not the same as every CodedSymbol being empty, see encoder::is_empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Grow (with empty CodedSymbols) or shrink the table to the provided length
+    pub fn resize(&mut self, len: usize) {
+        self.sums.resize(len * T::BYTE_ARRAY_LENGTH);
+        self.hashes.resize(len);
+        self.counts.resize(len);
+    }
+
+    /// Drop every CodedSymbol past the provided length
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            self.resize(len);
+        }
+    }
+
+    /// Append a copy of the CodedSymbol to the end of the table
+    pub fn push(&mut self, coded_symbol: &symbol::CodedSymbol<T>) {
+        assert_eq!(
+            coded_symbol.sum.len(),
+            T::BYTE_ARRAY_LENGTH,
+            "coded_symbol.sum must have the length specified by T::BYTE_ARRAY_LENGTH."
+        );
+        let index = self.len();
+        self.resize(index + 1);
+        self.sum_mut(index).copy_from_slice(&coded_symbol.sum);
+        self.hashes.as_mut_slice()[index] = coded_symbol.hash;
+        self.counts.as_mut_slice()[index] = coded_symbol.count;
+    }
+
+    /// A copy of the CodedSymbol at the provided index
+    pub fn get(&self, index: usize) -> symbol::CodedSymbol<T> {
+        let mut coded_symbol = symbol::CodedSymbol::new();
+        coded_symbol.sum.copy_from_slice(self.sum(index));
+        coded_symbol.hash = self.hash(index);
+        coded_symbol.count = self.count(index);
+        coded_symbol
+    }
+
+    /// Copies of every CodedSymbol, in order
+    pub fn iter(&self) -> impl Iterator<Item = symbol::CodedSymbol<T>> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+
+    pub fn sum(&self, index: usize) -> &[u8] {
+        let start = index * T::BYTE_ARRAY_LENGTH;
+        &self.sums.as_slice()[start..start + T::BYTE_ARRAY_LENGTH]
+    }
+
+    pub fn hash(&self, index: usize) -> u64 {
+        self.hashes.as_slice()[index]
+    }
+
+    pub fn count(&self, index: usize) -> i64 {
+        self.counts.as_slice()[index]
+    }
+
+    fn sum_mut(&mut self, index: usize) -> &mut [u8] {
+        let start = index * T::BYTE_ARRAY_LENGTH;
+        &mut self.sums.as_mut_slice()[start..start + T::BYTE_ARRAY_LENGTH]
+    }
+
+    /// Same as CodedSymbol::apply, for the CodedSymbol at the provided index
+    pub fn apply(&mut self, index: usize, s: &T, direction: symbol::Direction) {
+        let encoded_s = s.encode_to_bytes();
+        self.apply_encoded(index, &encoded_s, s.hash_(), direction);
+    }
+
+    /// Same as apply, but with the symbol already encoded and hashed.
+    ///
+    /// A symbol is usually applied to many CodedSymbols, this saves encoding it for every one.
+    pub fn apply_encoded(
+        &mut self,
+        index: usize,
+        encoded_s: &[u8],
+        hash: u64,
+        direction: symbol::Direction,
+    ) {
+        assert_eq!(
+            encoded_s.len(),
+            T::BYTE_ARRAY_LENGTH,
+            "encoded_s must have the length specified by T::BYTE_ARRAY_LENGTH."
+        );
+        self.sum_mut(index)
+            .iter_mut()
+            .zip(encoded_s.iter())
+            .for_each(|(x, y)| *x ^= y);
+        self.hashes.as_mut_slice()[index] ^= hash;
+        let count = &mut self.counts.as_mut_slice()[index];
+        match direction {
+            symbol::Direction::Add => *count = count.wrapping_add(1),
+            symbol::Direction::Remove => *count = count.wrapping_sub(1),
+        };
+    }
+
+    /// A copy of the first len CodedSymbols, held in memory
+    ///
+    /// To copy into a memory mapped table instead, resize it and use copy_from.
+    pub fn prefix(&self, len: usize) -> Self {
+        CodedSymbolTable {
+            sums: Column::Memory(self.sums.as_slice()[..len * T::BYTE_ARRAY_LENGTH].to_vec()),
+            hashes: Column::Memory(self.hashes.as_slice()[..len].to_vec()),
+            counts: Column::Memory(self.counts.as_slice()[..len].to_vec()),
+            peeled_again_at: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Overwrite our CodedSymbols with copies of the other table's, for every index in the range.
+    ///
+    /// Both tables must hold at least range.end CodedSymbols.
+    pub fn copy_from(&mut self, other: &CodedSymbolTable<T>, range: Range<usize>) {
+        let sums_range = range.start * T::BYTE_ARRAY_LENGTH..range.end * T::BYTE_ARRAY_LENGTH;
+        self.sums.as_mut_slice()[sums_range.clone()]
+            .copy_from_slice(&other.sums.as_slice()[sums_range]);
+        self.hashes.as_mut_slice()[range.clone()]
+            .copy_from_slice(&other.hashes.as_slice()[range.clone()]);
+        self.counts.as_mut_slice()[range.clone()]
+            .copy_from_slice(&other.counts.as_slice()[range]);
+    }
+
+    /// Subtract the other table's CodedSymbols from ours, for every index in the range.
+    ///
+    /// This is CodedSymbol::collapse applied in place, one column at a time, so the whole range
+    /// is a single pass over contiguous memory with no allocations.
+    ///
+    /// Both tables must hold at least range.end CodedSymbols.
+    pub fn collapse_from(&mut self, other: &CodedSymbolTable<T>, range: Range<usize>) {
+        self.xor_from(other, range.clone());
+        self.counts.as_mut_slice()[range.clone()]
+            .iter_mut()
+            .zip(other.counts.as_slice()[range].iter())
+            .for_each(|(x, y)| *x = x.wrapping_sub(*y));
+    }
+
+    /// Add the other table's CodedSymbols to ours, for every index in the range.
+    ///
+    /// This is CodedSymbol::combine applied in place, see collapse_from.
+    /// The results are only valid if there were no duplicates between the original sets.
+    pub fn combine_from(&mut self, other: &CodedSymbolTable<T>, range: Range<usize>) {
+        self.xor_from(other, range.clone());
+        self.counts.as_mut_slice()[range.clone()]
+            .iter_mut()
+            .zip(other.counts.as_slice()[range].iter())
+            .for_each(|(x, y)| *x = x.wrapping_add(*y));
+    }
+
+    // The sums and hashes are XORed for both collapse and combine
+    fn xor_from(&mut self, other: &CodedSymbolTable<T>, range: Range<usize>) {
+        assert!(
+            range.end <= self.len() && range.end <= other.len(),
+            "range must be within both tables."
+        );
+        let sums_range = range.start * T::BYTE_ARRAY_LENGTH..range.end * T::BYTE_ARRAY_LENGTH;
+        self.sums.as_mut_slice()[sums_range.clone()]
+            .iter_mut()
+            .zip(other.sums.as_slice()[sums_range].iter())
+            .for_each(|(x, y)| *x ^= y);
+        self.hashes.as_mut_slice()[range.clone()]
+            .iter_mut()
+            .zip(other.hashes.as_slice()[range].iter())
+            .for_each(|(x, y)| *x ^= y);
+    }
+
+    /// Same as CodedSymbol::is_empty, for the CodedSymbol at the provided index
+    pub fn is_empty_at(&self, index: usize) -> bool {
+        self.count(index) == 0 && self.hash(index) == 0
+    }
+
+    /// Same as CodedSymbol::is_peelable, for the CodedSymbol at the provided index
+    pub fn is_peelable(&self, index: usize) -> bool {
+        self.peel_peek(index).is_some()
+    }
+
+    // Peeling stopped at the index because the symbol there had already been peeled, which only
+    // forged coded symbols cause. Only the first index is kept.
+    pub(crate) fn mark_peeled_again(&mut self, index: usize) {
+        self.peeled_again_at.get_or_insert(index);
+    }
+
+    pub(crate) fn peeled_again_at(&self) -> Option<usize> {
+        self.peeled_again_at
+    }
+
+    /// Same as CodedSymbol::peel_peek, for the CodedSymbol at the provided index
+    pub fn peel_peek(&self, index: usize) -> Option<symbol::Difference<T>> {
+        let count = self.count(index);
+        if count != 1 && count != -1 {
+            return None;
+        }
+        // check the hash over the bytes first, most cells that get this far aren't pure
+        let sum = self.sum(index);
+        if T::hash_encoded(sum).is_some_and(|hash| hash != self.hash(index)) {
+            return None;
+        }
+        let symbol = T::decode_from_bytes(&sum.to_vec());
+        if symbol.hash_() != self.hash(index) {
+            return None;
+        }
+        if count == 1 {
+            Some(symbol::Difference::Local(symbol))
+        } else {
+            Some(symbol::Difference::Remote(symbol))
+        }
+    }
+}
+
+impl<T> Clone for CodedSymbolTable<T>
+where
+    T: symbol::Symbol,
+{
+    /// The clone is always held in memory, even if this table is memory mapped
+    fn clone(&self) -> Self {
+        CodedSymbolTable {
+            sums: self.sums.clone(),
+            hashes: self.hashes.clone(),
+            counts: self.counts.clone(),
+            peeled_again_at: self.peeled_again_at,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for CodedSymbolTable<T>
+where
+    T: symbol::Symbol,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<symbol::CodedSymbol<T>> for CodedSymbolTable<T>
+where
+    T: symbol::Symbol,
+{
+    fn from_iter<I: IntoIterator<Item = symbol::CodedSymbol<T>>>(iter: I) -> Self {
+        let mut table = CodedSymbolTable::new();
+        for coded_symbol in iter {
+            table.push(&coded_symbol);
+        }
+        table
+    }
+}
+
+impl<T> From<Vec<symbol::CodedSymbol<T>>> for CodedSymbolTable<T>
+where
+    T: symbol::Symbol,
+{
+    fn from(coded_symbols: Vec<symbol::CodedSymbol<T>>) -> Self {
+        coded_symbols.into_iter().collect()
+    }
+}
+
+// The element types a Column can hold.
+// Any bit pattern (including all zeroes) must be a valid value, as memory mapped files are
+// reinterpreted as slices of these.
+trait Plain: Copy + Default + 'static {}
+impl Plain for u8 {}
+impl Plain for u64 {}
+impl Plain for i64 {}
+
+// A contiguous, growable array, held in memory or in a memory mapped file
+enum Column<E: Plain> {
+    Memory(Vec<E>),
+    #[cfg(feature = "mmap")]
+    Mapped(mapped::MappedColumn<E>),
+}
+
+impl<E: Plain> Column<E> {
+    fn memory() -> Self {
+        Column::Memory(Vec::new())
+    }
+
+    #[cfg(feature = "mmap")]
+    fn mapped(path: &std::path::Path) -> std::io::Result<Self> {
+        Ok(Column::Mapped(mapped::MappedColumn::create(path)?))
+    }
+
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn as_slice(&self) -> &[E] {
+        match self {
+            Column::Memory(values) => values,
+            #[cfg(feature = "mmap")]
+            Column::Mapped(column) => column.as_slice(),
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [E] {
+        match self {
+            Column::Memory(values) => values,
+            #[cfg(feature = "mmap")]
+            Column::Mapped(column) => column.as_mut_slice(),
+        }
+    }
+
+    // New elements are always zero
+    fn resize(&mut self, len: usize) {
+        match self {
+            Column::Memory(values) => values.resize(len, E::default()),
+            #[cfg(feature = "mmap")]
+            Column::Mapped(column) => column.resize(len),
+        }
+    }
+}
+
+impl<E: Plain> Clone for Column<E> {
+    fn clone(&self) -> Self {
+        Column::Memory(self.as_slice().to_vec())
+    }
+}
+
+#[cfg(feature = "mmap")]
+mod mapped {
+    use super::Plain;
+    use memmap2::MmapMut;
+    use std::fs::{File, OpenOptions};
+    use std::marker::PhantomData;
+    use std::mem::size_of;
+    use std::path::Path;
+
+    // A column stored in a memory mapped file.
+    // The file grows by doubling, so growing one element at a time doesn't remap every time.
+    pub(super) struct MappedColumn<E: Plain> {
+        file: File,
+        // None until the file has a non-zero length, as empty files can't be mapped
+        map: Option<MmapMut>,
+        len: usize,
+        capacity: usize,
+        _marker: PhantomData<E>,
+    }
+
+    impl<E: Plain> MappedColumn<E> {
+        pub(super) fn create(path: &Path) -> std::io::Result<Self> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+            Ok(MappedColumn {
+                file,
+                map: None,
+                len: 0,
+                capacity: 0,
+                _marker: PhantomData,
+            })
+        }
+
+        pub(super) fn as_slice(&self) -> &[E] {
+            match &self.map {
+                // Safety: the map is page aligned and at least capacity elements long, and
+                // every bit pattern is a valid E
+                Some(map) => unsafe {
+                    std::slice::from_raw_parts(map.as_ptr() as *const E, self.len)
+                },
+                None => &[],
+            }
+        }
+
+        pub(super) fn as_mut_slice(&mut self) -> &mut [E] {
+            match &mut self.map {
+                // Safety: as for as_slice
+                Some(map) => unsafe {
+                    std::slice::from_raw_parts_mut(map.as_mut_ptr() as *mut E, self.len)
+                },
+                None => &mut [],
+            }
+        }
+
+        pub(super) fn resize(&mut self, len: usize) {
+            if len > self.capacity {
+                let capacity = usize::max(len, self.capacity * 2);
+                // Unmap before changing the length of the file
+                self.map = None;
+                let mapped = self
+                    .file
+                    .set_len((capacity * size_of::<E>()) as u64)
+                    // Safety: the file was created by us, it should not be modified elsewhere
+                    .and_then(|_| unsafe { MmapMut::map_mut(&self.file) });
+                match mapped {
+                    Ok(map) => self.map = Some(map),
+                    Err(err) => panic!("failed to grow memory mapped column: {}", err),
+                }
+                self.capacity = capacity;
+            } else if len < self.len {
+                // Growing the file zeroes the new space, shrinking needs to zero it ourselves
+                self.as_mut_slice()[len..].fill(E::default());
+            }
+            self.len = len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::SimpleSymbol;
+
+    fn check_table(table: &mut CodedSymbolTable<SimpleSymbol>) {
+        let symbol = SimpleSymbol { value: 42 };
+        let mut coded_symbol = symbol::CodedSymbol::new();
+        coded_symbol.apply(&symbol, symbol::Direction::Add);
+
+        table.resize(3);
+        table.push(&coded_symbol);
+        table.apply(1, &symbol, symbol::Direction::Remove);
+        assert_eq!(table.len(), 4);
+
+        assert!(table.is_empty_at(0));
+        assert_eq!(table.peel_peek(1), Some(symbol::Difference::Remote(symbol.clone())));
+        assert!(table.is_empty_at(2));
+        assert_eq!(table.peel_peek(3), Some(symbol::Difference::Local(symbol.clone())));
+
+        // Shrinking then growing again gives empty CodedSymbols
+        table.truncate(1);
+        table.resize(4);
+        assert!((0..4).all(|i| table.is_empty_at(i) && table.sum(i).iter().all(|&x| x == 0)));
+
+        // Growing one at a time
+        for _ in 0..1000 {
+            table.push(&coded_symbol);
+        }
+        assert_eq!(table.len(), 1004);
+        assert!(table.iter().skip(4).all(|c| c.is_peelable()));
+    }
+
+    #[test]
+    fn test_collapse_and_combine_from() {
+        let mut table_a = CodedSymbolTable::new();
+        let mut table_b = CodedSymbolTable::new();
+        for value in 0..10 {
+            let mut coded_symbol = symbol::CodedSymbol::new();
+            coded_symbol.apply(&SimpleSymbol { value }, symbol::Direction::Add);
+            table_a.push(&coded_symbol);
+            coded_symbol.apply(&SimpleSymbol { value: value * 3 }, symbol::Direction::Add);
+            table_b.push(&coded_symbol);
+        }
+
+        let mut collapsed = table_a.clone();
+        collapsed.collapse_from(&table_b, 2..7);
+        let mut combined = table_a.prefix(8);
+        combined.combine_from(&table_b, 2..7);
+        assert_eq!(combined.len(), 8);
+
+        for i in 0..8 {
+            let (a, b) = (table_a.get(i), table_b.get(i));
+            let (expected_collapsed, expected_combined) = if (2..7).contains(&i) {
+                (a.collapse(&b), a.combine(&b))
+            } else {
+                (a.clone(), a)
+            };
+            for (actual, expected) in [
+                (collapsed.get(i), expected_collapsed),
+                (combined.get(i), expected_combined),
+            ] {
+                assert_eq!(
+                    (actual.sum, actual.hash, actual.count),
+                    (expected.sum, expected.hash, expected.count)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_memory_table() {
+        check_table(&mut CodedSymbolTable::new());
+    }
+
+    // Hashes differently to the default, so peel_peek can't check the hash before decoding
+    #[derive(Clone, Debug, PartialEq)]
+    struct CustomHash(u64);
+
+    impl symbol::Symbol for CustomHash {
+        const BYTE_ARRAY_LENGTH: usize = 8;
+        const HASH_SCHEME: u32 = 1000;
+
+        fn encode_to_bytes(&self) -> Vec<u8> {
+            self.0.to_le_bytes().to_vec()
+        }
+
+        fn decode_from_bytes(bytes: &Vec<u8>) -> Self {
+            CustomHash(u64::from_le_bytes(bytes[..8].try_into().unwrap()))
+        }
+
+        fn hash_(&self) -> u64 {
+            self.0.wrapping_mul(0x9e3779b97f4a7c15)
+        }
+    }
+
+    #[test]
+    fn test_peel_peek_custom_hash() {
+        assert_eq!(<CustomHash as symbol::Symbol>::hash_encoded(&[0u8; 8]), None);
+        let mut table = CodedSymbolTable::new();
+        table.resize(2);
+        table.apply(0, &CustomHash(7), symbol::Direction::Add);
+        table.apply(1, &CustomHash(7), symbol::Direction::Add);
+        table.apply(1, &CustomHash(8), symbol::Direction::Add);
+        assert_eq!(table.peel_peek(0), Some(symbol::Difference::Local(CustomHash(7))));
+        assert_eq!(table.peel_peek(1), None);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_table() {
+        let dir = std::env::temp_dir().join(format!("riblt-table-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut table = CodedSymbolTable::with_mmap_dir(&dir).unwrap();
+        check_table(&mut table);
+        let cloned = table.clone();
+        assert_eq!(cloned.len(), table.len());
+
+        drop(table);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
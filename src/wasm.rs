@@ -0,0 +1,174 @@
+//! JavaScript bindings, built with the wasm-bindgen feature for wasm32-unknown-unknown.
+//!
+//! Symbols are byte strings of a fixed width, chosen when the Encoder or Decoder is created.
+//! Sets are passed in as a single Uint8Array holding every symbol one after the other, and coded
+//! symbols are Uint8Arrays in the wire format (see write_coded_symbol).
+//!
+//! ```js
+//! const local = new Encoder(8, localIds);
+//! const decoder = new Decoder(8);
+//! while (decoder.length === 0 || !decoder.isEmpty()) {
+//!     const remote = await fetchCodedSymbol(decoder.length);
+//!     decoder.addCodedSymbol(local.collapseCodedSymbol(decoder.length, remote));
+//! }
+//! for (let d = decoder.nextDifference(); d; d = decoder.nextDifference()) {
+//!     d.local ? sendToServer(d.symbol) : requestFromServer(d.symbol);
+//! }
+//! ```
+
+use crate::dynamic::{
+    coded_symbol_len, AnyDecoder, AnyEncoder, ConcatenatedSymbols, SUPPORTED_WIDTHS,
+};
+use crate::error::Error;
+use crate::symbol;
+use wasm_bindgen::prelude::*;
+
+fn unsupported_width(width: usize) -> JsError {
+    JsError::new(&format!(
+        "symbols of {} bytes are not supported, use one of {:?}",
+        width, SUPPORTED_WIDTHS
+    ))
+}
+
+/// The number of bytes in each coded symbol for symbols of the width, for framing a stream of
+/// them
+#[wasm_bindgen(js_name = codedSymbolLength)]
+pub fn coded_symbol_length(width: usize) -> Result<usize, JsError> {
+    if !SUPPORTED_WIDTHS.contains(&width) {
+        return Err(unsupported_width(width));
+    }
+    Ok(coded_symbol_len(width))
+}
+
+/// Generates coded symbols for a local set, and collapses a remote's coded symbols against them
+#[wasm_bindgen]
+pub struct Encoder(AnyEncoder<ConcatenatedSymbols>);
+
+#[wasm_bindgen]
+impl Encoder {
+    /// symbols holds every symbol in the set, width bytes each. They are copied.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, symbols: &[u8]) -> Result<Encoder, JsError> {
+        if width == 0 || !symbols.len().is_multiple_of(width) {
+            return Err(JsError::new(
+                "the symbols must be a multiple of width bytes long",
+            ));
+        }
+        AnyEncoder::new(width, ConcatenatedSymbols(symbols.to_vec()))
+            .map(Encoder)
+            .ok_or_else(|| unsupported_width(width))
+    }
+
+    /// The coded symbol at the index, to stream to a remote
+    #[wasm_bindgen(js_name = codedSymbol)]
+    pub fn coded_symbol(&mut self, index: usize) -> Result<Vec<u8>, JsError> {
+        Ok(self.0.get_coded_symbol(index)?)
+    }
+
+    /// Our coded symbol at the index minus the remote's, ready for Decoder.addCodedSymbol
+    #[wasm_bindgen(js_name = collapseCodedSymbol)]
+    pub fn collapse_coded_symbol(
+        &mut self,
+        index: usize,
+        remote: &[u8],
+    ) -> Result<Vec<u8>, JsError> {
+        Ok(self.0.collapse_coded_symbol(index, remote)?)
+    }
+
+    /// Compare with the remote's fingerprint before streaming, if they are equal the sets are
+    /// the same
+    pub fn fingerprint(&self) -> Result<Fingerprint, JsError> {
+        Ok(Fingerprint(self.0.fingerprint()?))
+    }
+}
+
+/// See Fingerprint in the Rust docs
+#[wasm_bindgen]
+pub struct Fingerprint(symbol::Fingerprint);
+
+#[wasm_bindgen]
+impl Fingerprint {
+    #[wasm_bindgen(getter)]
+    pub fn hash(&self) -> u64 {
+        self.0.hash
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn count(&self) -> i64 {
+        self.0.count
+    }
+
+    pub fn equals(&self, other: &Fingerprint) -> bool {
+        self.0 == other.0
+    }
+}
+
+/// Peels the difference out of collapsed coded symbols as they arrive
+#[wasm_bindgen]
+pub struct Decoder(AnyDecoder);
+
+#[wasm_bindgen]
+impl Decoder {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize) -> Result<Decoder, JsError> {
+        AnyDecoder::new(width)
+            .map(Decoder)
+            .ok_or_else(|| unsupported_width(width))
+    }
+
+    /// Add the next collapsed coded symbol (see Encoder.collapseCodedSymbol)
+    #[wasm_bindgen(js_name = addCodedSymbol)]
+    pub fn add_coded_symbol(&mut self, coded_symbol: &[u8]) -> Result<(), JsError> {
+        self.0
+            .add_coded_symbol(coded_symbol)
+            .map_err(|err: Error| JsError::new(&err.to_string()))
+    }
+
+    /// The next difference peeled so far, or undefined. Each difference is only returned once.
+    #[wasm_bindgen(js_name = nextDifference)]
+    pub fn next_difference(&mut self) -> Option<Difference> {
+        self.0.next_difference().map(|difference| Difference {
+            local: difference.local,
+            symbol: difference.symbol,
+        })
+    }
+
+    /// true once every coded symbol added is empty, meaning the whole difference has been peeled
+    #[wasm_bindgen(js_name = isEmpty)]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The width of the symbols, in bytes
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        self.0.width()
+    }
+
+    /// The number of coded symbols added, which is also the index of the next one to add
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// A symbol that is only in one of the sets
+#[wasm_bindgen]
+pub struct Difference {
+    local: bool,
+    symbol: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Difference {
+    /// true if the symbol is only in the local set, false if only in the remote set
+    #[wasm_bindgen(getter)]
+    pub fn local(&self) -> bool {
+        self.local
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn symbol(&self) -> Vec<u8> {
+        self.symbol.clone()
+    }
+}
@@ -0,0 +1,125 @@
+use crate::error::Error;
+use crate::symbol;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+
+/// The number of bytes a CodedSymbol takes up on the wire
+///
+/// The 'sum' is written as is, followed by the 'hash' and 'count' as little endian.
+pub fn encoded_len<T: symbol::Symbol>() -> usize {
+    T::BYTE_ARRAY_LENGTH + 16
+}
+
+/// Write a CodedSymbol in a fixed size, platform independent layout.
+///
+/// CodedSymbol also implements serde's Serialize if you would rather use a serialization library.
+#[cfg(feature = "std")]
+pub fn write_coded_symbol<T: symbol::Symbol, W: Write>(
+    writer: &mut W,
+    coded_symbol: &symbol::CodedSymbol<T>,
+) -> std::io::Result<()> {
+    writer.write_all(&coded_symbol.sum)?;
+    writer.write_all(&coded_symbol.hash.to_le_bytes())?;
+    writer.write_all(&coded_symbol.count.to_le_bytes())
+}
+
+/// Read a CodedSymbol written by write_coded_symbol.
+///
+/// The 'sum' is always T::BYTE_ARRAY_LENGTH bytes, so the result is safe to peel.
+#[cfg(feature = "std")]
+pub fn read_coded_symbol<T: symbol::Symbol, R: Read>(
+    reader: &mut R,
+) -> std::io::Result<symbol::CodedSymbol<T>> {
+    let mut coded_symbol = symbol::CodedSymbol::new();
+    reader.read_exact(&mut coded_symbol.sum)?;
+    coded_symbol.hash = u64::from_le_bytes(read_array(reader)?);
+    coded_symbol.count = i64::from_le_bytes(read_array(reader)?);
+    Ok(coded_symbol)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn read_array<const N: usize, R: Read>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut buffer = [0u8; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// The same layout as write_coded_symbol, into a new buffer. This doesn't need std.
+pub fn encode_coded_symbol<T: symbol::Symbol>(coded_symbol: &symbol::CodedSymbol<T>) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(encoded_len::<T>());
+    buffer.extend_from_slice(&coded_symbol.sum);
+    buffer.extend_from_slice(&coded_symbol.hash.to_le_bytes());
+    buffer.extend_from_slice(&coded_symbol.count.to_le_bytes());
+    buffer
+}
+
+/// The opposite of encode_coded_symbol, the bytes must be exactly encoded_len long.
+pub fn decode_coded_symbol<T: symbol::Symbol>(
+    bytes: &[u8],
+) -> Result<symbol::CodedSymbol<T>, Error> {
+    if bytes.len() != encoded_len::<T>() {
+        return Err(Error::InvalidFormat("coded symbol has the wrong length"));
+    }
+    let (sum, rest) = bytes.split_at(T::BYTE_ARRAY_LENGTH);
+    let (hash, count) = rest.split_at(8);
+    let mut coded_symbol = symbol::CodedSymbol::new();
+    coded_symbol.sum.copy_from_slice(sum);
+    coded_symbol.hash = u64::from_le_bytes(hash.try_into().unwrap());
+    coded_symbol.count = i64::from_le_bytes(count.try_into().unwrap());
+    Ok(coded_symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::SimpleSymbol;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_round_trip() {
+        let mut coded_symbol: symbol::CodedSymbol<SimpleSymbol> = symbol::CodedSymbol::new();
+        coded_symbol.apply(&SimpleSymbol { value: 7 }, symbol::Direction::Add);
+        coded_symbol.apply(&SimpleSymbol { value: 9 }, symbol::Direction::Remove);
+        coded_symbol.apply(&SimpleSymbol { value: 11 }, symbol::Direction::Remove);
+
+        let mut buffer = Vec::new();
+        write_coded_symbol(&mut buffer, &coded_symbol).unwrap();
+        assert_eq!(buffer.len(), encoded_len::<SimpleSymbol>());
+
+        let decoded: symbol::CodedSymbol<SimpleSymbol> =
+            read_coded_symbol(&mut buffer.as_slice()).unwrap();
+        assert_eq!(decoded.sum, coded_symbol.sum);
+        assert_eq!(decoded.hash, coded_symbol.hash);
+        assert_eq!(decoded.count, -1);
+
+        // Not enough bytes
+        assert!(read_coded_symbol::<SimpleSymbol, _>(&mut &buffer[..10]).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut coded_symbol: symbol::CodedSymbol<SimpleSymbol> = symbol::CodedSymbol::new();
+        coded_symbol.apply(&SimpleSymbol { value: 7 }, symbol::Direction::Add);
+        coded_symbol.apply(&SimpleSymbol { value: 9 }, symbol::Direction::Add);
+
+        let encoded = encode_coded_symbol(&coded_symbol);
+        assert_eq!(encoded.len(), encoded_len::<SimpleSymbol>());
+        #[cfg(feature = "std")]
+        {
+            let mut written = Vec::new();
+            write_coded_symbol(&mut written, &coded_symbol).unwrap();
+            assert_eq!(encoded, written);
+        }
+
+        let decoded: symbol::CodedSymbol<SimpleSymbol> = decode_coded_symbol(&encoded).unwrap();
+        assert_eq!(decoded.sum, coded_symbol.sum);
+        assert_eq!(decoded.hash, coded_symbol.hash);
+        assert_eq!(decoded.count, 2);
+
+        assert!(matches!(
+            decode_coded_symbol::<SimpleSymbol>(&encoded[1..]),
+            Err(Error::InvalidFormat(_))
+        ));
+    }
+}
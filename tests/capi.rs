@@ -0,0 +1,56 @@
+#![cfg(feature = "capi")]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// The staticlib isn't one of the crate's default crate types (no_std users can't link one), so
+// build it the same way C users do, in a target directory of its own.
+fn build_staticlib(manifest_dir: &Path) -> PathBuf {
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("capi");
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "rustc",
+            "--lib",
+            "--features",
+            "capi",
+            "--crate-type",
+            "staticlib",
+        ])
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    target_dir.join("debug/libriblt.a")
+}
+
+// Compile tests/capi_test.c against include/riblt.h and the staticlib, then run it.
+#[test]
+fn test_c_api() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let staticlib = build_staticlib(&manifest_dir);
+    let exe = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join(format!("capi_test-{}", std::process::id()));
+
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(compiler)
+        .args(["-std=c99", "-Wall", "-Werror"])
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("tests/capi_test.c"))
+        .arg(&staticlib)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&exe).output().unwrap();
+    std::fs::remove_file(&exe).unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("recovered 10 local and 5 remote ids"));
+}
@@ -0,0 +1,130 @@
+/* Compiled and run by tests/capi.rs, linked against the riblt staticlib. */
+
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "riblt.h"
+
+#define WIDTH 8
+
+/* A set of 64 bit ids in [start, end), written as 8 little endian bytes */
+struct range_set {
+    uint64_t start;
+    uint64_t end;
+    uint64_t next;
+    int fail;
+};
+
+static int range_rewind(void *context) {
+    struct range_set *set = context;
+    set->next = set->start;
+    return set->fail ? -7 : 0;
+}
+
+static int range_next(void *context, uint8_t *symbol) {
+    struct range_set *set = context;
+    if (set->next == set->end) {
+        return 0;
+    }
+    for (int i = 0; i < WIDTH; i++) {
+        symbol[i] = (uint8_t)(set->next >> (8 * i));
+    }
+    set->next++;
+    return 1;
+}
+
+static uint64_t read_id(const uint8_t *symbol) {
+    uint64_t id = 0;
+    for (int i = 0; i < WIDTH; i++) {
+        id |= (uint64_t)symbol[i] << (8 * i);
+    }
+    return id;
+}
+
+int main(void) {
+    size_t len = riblt_coded_symbol_len(WIDTH);
+    assert(len == WIDTH + 16);
+    assert(riblt_coded_symbol_len(7) == 0);
+
+    /* local has 0..1000, remote has 10..1005 */
+    struct range_set local_set = {0, 1000, 0, 0};
+    struct range_set remote_set = {10, 1005, 0, 0};
+    RibltSetIterator local_iterator = {&local_set, range_rewind, range_next};
+    RibltSetIterator remote_iterator = {&remote_set, range_rewind, range_next};
+
+    RibltEncoder *local = riblt_encoder_new(WIDTH, local_iterator);
+    RibltEncoder *remote = riblt_encoder_new(WIDTH, remote_iterator);
+    RibltDecoder *decoder = riblt_decoder_new(WIDTH);
+    assert(local && remote && decoder);
+    assert(riblt_encoder_new(7, local_iterator) == NULL);
+
+    /* Different sets have different fingerprints */
+    uint64_t local_hash, remote_hash;
+    int64_t local_count, remote_count;
+    assert(riblt_encoder_fingerprint(local, &local_hash, &local_count) == RIBLT_OK);
+    assert(riblt_encoder_fingerprint(remote, &remote_hash, &remote_count) == RIBLT_OK);
+    assert(local_count == 1000 && remote_count == 995 && local_hash != remote_hash);
+
+    uint8_t coded_symbol[WIDTH + 16];
+    uint8_t collapsed[WIDTH + 16];
+    int is_empty;
+    do {
+        size_t index = riblt_decoder_len(decoder);
+        assert(riblt_encoder_get_coded_symbol(remote, index, coded_symbol, len) == RIBLT_OK);
+        assert(riblt_encoder_collapse_coded_symbol(local, index, coded_symbol, len, collapsed,
+                                                   len) == RIBLT_OK);
+        assert(riblt_decoder_add_coded_symbol(decoder, collapsed, len) == RIBLT_OK);
+        assert(riblt_decoder_is_empty(decoder, &is_empty) == RIBLT_OK);
+    } while (!is_empty);
+    size_t index = riblt_decoder_len(decoder);
+
+    int local_only = 0, remote_only = 0;
+    uint8_t symbol[WIDTH];
+    int result;
+    while ((result = riblt_decoder_next_difference(decoder, symbol, WIDTH)) != RIBLT_NO_DIFFERENCE) {
+        uint64_t id = read_id(symbol);
+        if (result == RIBLT_LOCAL) {
+            assert(id < 10);
+            local_only++;
+        } else {
+            assert(result == RIBLT_REMOTE);
+            assert(id >= 1000 && id < 1005);
+            remote_only++;
+        }
+    }
+    assert(local_only == 10 && remote_only == 5);
+    printf("recovered %d local and %d remote ids from %zu coded symbols\n", local_only,
+           remote_only, index);
+
+    /* Bad arguments */
+    assert(riblt_encoder_get_coded_symbol(remote, 0, coded_symbol, len - 1) ==
+           RIBLT_ERROR_INVALID_ARGUMENT);
+    assert(riblt_decoder_add_coded_symbol(decoder, NULL, len) == RIBLT_ERROR_INVALID_ARGUMENT);
+    assert(riblt_decoder_is_empty(NULL, &is_empty) == RIBLT_ERROR_INVALID_ARGUMENT);
+    assert(riblt_decoder_is_empty(decoder, NULL) == RIBLT_ERROR_INVALID_ARGUMENT);
+
+    /* An encoder over a copy of the same ids in a buffer produces the same coded symbols */
+    uint8_t buffer[995 * WIDTH];
+    range_rewind(&remote_set);
+    for (size_t i = 0; i < 995; i++) {
+        range_next(&remote_set, buffer + i * WIDTH);
+    }
+    RibltEncoder *copied = riblt_encoder_new_from_buffer(WIDTH, buffer, sizeof(buffer));
+    assert(copied);
+    assert(riblt_encoder_new_from_buffer(WIDTH, buffer, sizeof(buffer) - 1) == NULL);
+    uint8_t copied_coded_symbol[WIDTH + 16];
+    assert(riblt_encoder_get_coded_symbol(copied, 3, copied_coded_symbol, len) == RIBLT_OK);
+    assert(riblt_encoder_get_coded_symbol(remote, 3, coded_symbol, len) == RIBLT_OK);
+    assert(memcmp(copied_coded_symbol, coded_symbol, len) == 0);
+    riblt_encoder_free(copied);
+
+    /* Errors from the callbacks are reported, past the coded symbols we already have */
+    remote_set.fail = 1;
+    assert(riblt_encoder_get_coded_symbol(remote, 5000, coded_symbol, len) == RIBLT_ERROR_SET);
+
+    riblt_encoder_free(local);
+    riblt_encoder_free(remote);
+    riblt_decoder_free(decoder);
+    return 0;
+}
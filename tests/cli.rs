@@ -0,0 +1,223 @@
+#![cfg(feature = "cli")]
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+fn riblt(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_riblt"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// Write the lines to a file in a directory unique to this test
+fn write_lines(test: &str, name: &str, lines: impl Iterator<Item = String>) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("riblt-cli-{}-{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let contents: Vec<String> = lines.collect();
+    std::fs::write(&path, contents.join("\n")).unwrap();
+    path
+}
+
+fn encode(file: &Path, count: usize, stream: &Path) {
+    let output = riblt(&["encode", file.to_str().unwrap(), "--count", &count.to_string()]);
+    assert!(output.status.success(), "{:?}", output);
+    std::fs::write(stream, output.stdout).unwrap();
+}
+
+#[test]
+fn test_diff() {
+    let local = write_lines("diff", "local.txt", (0..1000).map(|i| format!("id-{}", i)));
+    let remote = write_lines("diff", "remote.txt", (3..1002).map(|i| format!("id-{}", i)));
+    let stream = local.with_file_name("remote.riblt");
+    encode(&remote, 100, &stream);
+
+    let output = riblt(&["diff", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut removed: Vec<&str> = stdout.lines().filter_map(|l| l.strip_prefix("- ")).collect();
+    removed.sort();
+    assert_eq!(removed, vec!["id-0", "id-1", "id-2"]);
+    let added: Vec<&str> = stdout.lines().filter_map(|l| l.strip_prefix("+ ")).collect();
+    assert_eq!(added.len(), 2);
+    assert!(added.iter().all(|digest| digest.len() == 64));
+
+    // Diffing against ourselves stops at the header
+    encode(&local, 100, &stream);
+    let output = riblt(&["diff", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    std::fs::remove_dir_all(local.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_stream_too_short() {
+    let local = write_lines("short", "local.txt", (0..500).map(|i| format!("id-{}", i)));
+    let remote = write_lines("short", "remote.txt", (100..600).map(|i| format!("id-{}", i)));
+    let stream = local.with_file_name("remote.riblt");
+    encode(&remote, 10, &stream);
+
+    let output = riblt(&["diff", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("--count"));
+
+    // Only the header, the fingerprints differ but there is nothing to peel
+    encode(&remote, 0, &stream);
+    let output = riblt(&["diff", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("--count"));
+
+    std::fs::remove_dir_all(local.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_stats() {
+    let local = write_lines("stats", "local.txt", (0..1000).map(|i| format!("id-{}", i)));
+    let remote = write_lines("stats", "remote.txt", (10..1000).map(|i| format!("id-{}", i)));
+    let stream = local.with_file_name("remote.riblt");
+    encode(&remote, 200, &stream);
+
+    let output = riblt(&["stats", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("local lines: 1000"));
+    assert!(stdout.contains("remote lines: 990"));
+    assert!(stdout.contains("coded symbols in stream: 200"));
+    assert!(stdout.contains("local only: 10"));
+    assert!(stdout.contains("remote only: 0"));
+
+    std::fs::remove_dir_all(local.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_usage() {
+    let output = riblt(&["encode", "file.txt"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("usage"));
+}
+
+// riblt serve <remote> | riblt sync <local>
+fn serve_sync(remote: &Path, local: &Path) -> Output {
+    let mut serve = Command::new(env!("CARGO_BIN_EXE_riblt"))
+        .args(["serve", remote.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let sync = Command::new(env!("CARGO_BIN_EXE_riblt"))
+        .args(["sync", local.to_str().unwrap()])
+        .stdin(serve.stdout.take().unwrap())
+        .output()
+        .unwrap();
+    // serve stops once sync hangs up
+    assert!(serve.wait().unwrap().success());
+    sync
+}
+
+#[test]
+fn test_serve_sync() {
+    let local = write_lines("sync", "local.txt", (0..5000).map(|i| format!("id-{}", i)));
+    let remote = write_lines("sync", "remote.txt", (50..5020).map(|i| format!("id-{}", i)));
+
+    let output = serve_sync(&remote, &local);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let removed: HashSet<String> = stdout
+        .lines()
+        .filter_map(|l| l.strip_prefix("- "))
+        .map(String::from)
+        .collect();
+    assert_eq!(removed, (0..50).map(|i| format!("id-{}", i)).collect());
+    assert_eq!(stdout.lines().filter(|l| l.starts_with("+ ")).count(), 20);
+
+    // The same file, sync stops after the header
+    let output = serve_sync(&local, &local);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    std::fs::remove_dir_all(local.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_sync_stream_ends_early() {
+    let local = write_lines("early", "local.txt", (0..500).map(|i| format!("id-{}", i)));
+    let remote = write_lines("early", "remote.txt", (100..600).map(|i| format!("id-{}", i)));
+    let stream = local.with_file_name("remote.riblt");
+    encode(&remote, 10, &stream);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_riblt"))
+        .args(["sync", local.to_str().unwrap()])
+        .stdin(std::fs::File::open(&stream).unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("stream ended"));
+
+    std::fs::remove_dir_all(local.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_truncated_stream() {
+    let local = write_lines("truncated", "local.txt", (0..500).map(|i| format!("id-{}", i)));
+    let remote = write_lines("truncated", "remote.txt", (5..505).map(|i| format!("id-{}", i)));
+    let stream = local.with_file_name("remote.riblt");
+    encode(&remote, 100, &stream);
+
+    // Drop the last few bytes, so the stream ends part way through the last coded symbol
+    let mut bytes = std::fs::read(&stream).unwrap();
+    bytes.truncate(bytes.len() - 5);
+    std::fs::write(&stream, bytes).unwrap();
+
+    let output = riblt(&["diff", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("part way through a coded symbol"));
+
+    let output = riblt(&["stats", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // A stream too short for sync to finish before the truncation
+    encode(&remote, 3, &stream);
+    let mut bytes = std::fs::read(&stream).unwrap();
+    bytes.truncate(bytes.len() - 5);
+    std::fs::write(&stream, bytes).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_riblt"))
+        .args(["sync", local.to_str().unwrap()])
+        .stdin(std::fs::File::open(&stream).unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("part way through a coded symbol"));
+
+    std::fs::remove_dir_all(local.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_forged_local_difference() {
+    use riblt::{write_coded_symbol, ByteSymbol, CodedSymbol, Symbol};
+
+    let local = write_lines("forged", "local.txt", std::iter::empty());
+    let stream = local.with_file_name("remote.riblt");
+    // Just the header, of a different file
+    let remote = write_lines("forged", "remote.txt", ["b".to_string()].into_iter());
+    encode(&remote, 0, &stream);
+
+    // One coded symbol that takes away a digest that isn't in the local file, so it peels as if
+    // only the local file has it
+    let forged = ByteSymbol([7u8; 32]);
+    let mut coded_symbol = CodedSymbol::<ByteSymbol<32>>::new();
+    coded_symbol.sum = forged.encode_to_bytes();
+    coded_symbol.hash = forged.hash_();
+    coded_symbol.count = -1;
+    let mut bytes = std::fs::read(&stream).unwrap();
+    write_coded_symbol(&mut bytes, &coded_symbol).unwrap();
+    std::fs::write(&stream, bytes).unwrap();
+
+    let output = riblt(&["diff", local.to_str().unwrap(), stream.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    assert!(String::from_utf8(output.stderr).unwrap().contains("inconsistent"));
+
+    std::fs::remove_dir_all(local.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,100 @@
+# Tests for the Python module, run against the extension module maturin builds (src/python.rs).
+#
+# Run with:
+#   maturin develop && pytest tests/python
+# or without pytest, after maturin develop: python tests/python/test_riblt.py
+
+import riblt
+
+
+def ids(r):
+    return [i.to_bytes(8, "little") for i in r]
+
+
+def reconcile(local, remote, batch=5):
+    decoder = riblt.Decoder(local.width)
+    while len(decoder) == 0 or not decoder.is_empty():
+        start = len(decoder)
+        for index, cs in enumerate(remote.coded_symbols(start, start + batch)):
+            decoder.add(local.collapse_coded_symbol(start + index, cs))
+    return decoder
+
+
+def test_reconcile():
+    local_ids = ids(range(1000))
+    remote_ids = ids(range(10, 1005))
+    local = riblt.Encoder(local_ids)
+    remote = riblt.Encoder(remote_ids)
+    assert local.width == 8
+    assert local.fingerprint() != remote.fingerprint()
+    assert remote.fingerprint()[1] == 995
+
+    decoder = reconcile(local, remote)
+    local_only, remote_only = decoder.peel()
+    assert sorted(local_only) == local_ids[:10]
+    assert sorted(remote_only) == ids(range(1000, 1005))
+    # peel() returns everything peeled so far, not just the new differences
+    assert decoder.peel() == (local_only, remote_only)
+    assert decoder.width == 8
+
+
+def test_same_sets():
+    local = riblt.Encoder(ids(range(50)))
+    remote = riblt.Encoder(list(reversed(ids(range(50)))))
+    assert local.fingerprint() == remote.fingerprint()
+    decoder = reconcile(local, remote, batch=1)
+    assert len(decoder) == 1
+    assert decoder.peel() == ([], [])
+
+
+def test_wider_symbols():
+    local = riblt.Encoder([bytes([i]) * 32 for i in range(20)])
+    remote = riblt.Encoder([bytes([i]) * 32 for i in range(1, 20)], width=32)
+    local_only, remote_only = reconcile(local, remote).peel()
+    assert local_only == [bytes(32)]
+    assert remote_only == []
+
+
+def test_serialisation():
+    encoder = riblt.Encoder([b"abcd", b"efgh"])
+    cs = encoder.coded_symbols(0, 1)[0]
+    assert isinstance(cs, bytes)
+    assert len(cs) == 4 + 16
+    sum, hash, count = riblt.decode_coded_symbol(cs, 4)
+    assert count == 2
+    assert sum == bytes(a ^ b for a, b in zip(b"abcd", b"efgh"))
+    assert riblt.encode_coded_symbol(sum, hash, count) == cs
+
+    # coded symbols survive a round trip through their parts on the way to the decoder
+    local = riblt.Encoder(ids(range(10)))
+    remote = riblt.Encoder(ids(range(1, 10)))
+    decoder = riblt.Decoder(8)
+    for index in range(10):
+        parts = riblt.decode_coded_symbol(remote.coded_symbols(index, index + 1)[0], 8)
+        decoder.add(local.collapse_coded_symbol(index, riblt.encode_coded_symbol(*parts)))
+    assert decoder.is_empty()
+    assert decoder.peel() == (ids(range(1)), [])
+
+
+def test_invalid_arguments():
+    def raises(f):
+        try:
+            f()
+        except ValueError as err:
+            return str(err)
+        raise AssertionError("no ValueError")
+
+    assert "item 1 is 3 bytes" in raises(lambda: riblt.Encoder([b"abcd", b"abc"]))
+    assert "width" in raises(lambda: riblt.Encoder([]))
+    assert riblt.Encoder([], width=4).fingerprint() == (0, 0)
+    raises(lambda: riblt.Encoder([b"abc"]))
+    raises(lambda: riblt.Decoder(7))
+    raises(lambda: riblt.Decoder(4).add(b"short"))
+    raises(lambda: riblt.decode_coded_symbol(b"short", 4))
+
+
+if __name__ == "__main__":
+    for name, test in list(globals().items()):
+        if name.startswith("test_"):
+            test()
+            print(name, "ok")
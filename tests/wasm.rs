@@ -0,0 +1,49 @@
+// Run under node with: wasm-pack test --node -- --features wasm-bindgen
+// or, with wasm-bindgen-cli installed: cargo test --target wasm32-unknown-unknown --features wasm-bindgen --test wasm
+#![cfg(all(feature = "wasm-bindgen", target_arch = "wasm32"))]
+
+use riblt::wasm::{Decoder, Encoder};
+use wasm_bindgen_test::*;
+
+fn concatenated(ids: std::ops::Range<u64>) -> Vec<u8> {
+    ids.flat_map(|id| id.to_le_bytes()).collect()
+}
+
+#[wasm_bindgen_test]
+fn test_encoder_and_decoder() {
+    let mut local = Encoder::new(8, &concatenated(0..100)).unwrap();
+    let mut remote = Encoder::new(8, &concatenated(3..102)).unwrap();
+    assert!(!local
+        .fingerprint()
+        .unwrap()
+        .equals(&remote.fingerprint().unwrap()));
+
+    let mut decoder = Decoder::new(8).unwrap();
+    while decoder.length() == 0 || !decoder.is_empty() {
+        let coded_symbol = remote.coded_symbol(decoder.length()).unwrap();
+        let collapsed = local
+            .collapse_coded_symbol(decoder.length(), &coded_symbol)
+            .unwrap();
+        decoder.add_coded_symbol(&collapsed).unwrap();
+    }
+
+    let mut differences = Vec::new();
+    while let Some(difference) = decoder.next_difference() {
+        let id = u64::from_le_bytes(difference.symbol().try_into().unwrap());
+        differences.push((difference.local(), id));
+    }
+    differences.sort_by_key(|&(_, id)| id);
+    assert_eq!(
+        differences,
+        vec![(true, 0), (true, 1), (true, 2), (false, 100), (false, 101)]
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_invalid_arguments() {
+    assert!(Encoder::new(7, &[0; 14]).is_err());
+    assert!(Encoder::new(8, &[0; 12]).is_err());
+    assert!(Decoder::new(0).is_err());
+    let mut decoder = Decoder::new(8).unwrap();
+    assert!(decoder.add_coded_symbol(&[0; 3]).is_err());
+}